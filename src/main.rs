@@ -1,345 +1,3954 @@
 use anyhow::{Context, Result};
 use ncurses::*;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
 use chrono::Utc;
 
-const ROOT: &str = "/home/pdc/dev";
 const CONFIG_NAME: &str = "fylex.config.json";
+// Free-form notes kept next to the config, see edit_notes
+const NOTES_NAME: &str = ".fylex-notes.md";
+// How much of the notes the details pane shows
+const NOTES_LINES: usize = 5;
+const DEFAULT_ROOT: &str = "~/dev";
+// Bump when GitCache changes shape; older files are discarded
+const GIT_CACHE_VERSION: u32 = 2;
+// Commits listed under "Recent" in the details
+const RECENT_COMMITS: usize = 5;
+// How much of a README the preview keeps
+const README_LINES: usize = 200;
 
+// Shown by the ? overlay
+const KEYBINDINGS: &[(&str, &str)] = &[
+    ("Enter", "open in terminal (tmux session under tmux)"),
+    ("c", "open in $VISUAL / $EDITOR"),
+    ("O", "open folder in the file manager"),
+    ("u", "open the repository's web page (repo_url or origin)"),
+    ("/", "filter (Enter / Esc to leave), re:<regex> for a regex"),
+    ("Ctrl+S", "case-sensitive filter on / off"),
+    ("Esc", "unlock the details, then clear the filter, then the marks"),
+    ("Space", "mark / unmark (a and p act on marked projects)"),
+    ("j k / arrows", "move"),
+    ("g G", "first / last project"),
+    ("PgUp PgDn", "move by a page"),
+    ("J K", "scroll the README preview"),
+    ("l", "lock the details to this project (l / Esc unlocks)"),
+    ("s", "cycle sort order"),
+    ("H", "group by creation date on / off"),
+    ("i", "branch and tags in the list on / off"),
+    ("@", "relative / absolute dates"),
+    ("# 1-9", "filter by one of the project's tags"),
+    ("*", "pin / unpin"),
+    ("a", "add config (defaults for marked projects)"),
+    ("F", "edit fylex.config.json in $EDITOR"),
+    ("T", "edit tags"),
+    ("E", "edit name"),
+    ("D", "edit description"),
+    ("n", "edit notes (.fylex-notes.md) in $EDITOR"),
+    ("N", "new project"),
+    ("L", "new project cloned from a git URL"),
+    ("+", "new project copied from this one"),
+    ("M", "rename directory"),
+    ("X", "delete project"),
+    ("Z", "archive / unarchive"),
+    ("A", "show / hide archived"),
+    (".", "show / hide .dot folders"),
+    ("y", "copy the path to the clipboard"),
+    ("B", "pack into a .tar.gz (without .git and build output)"),
+    ("b", "run the build command"),
+    ("!", "run one of the project's commands"),
+    ("p", "git pull (fast-forward only)"),
+    ("C", "git commit -am"),
+    ("S P", "git stash / stash pop"),
+    ("v V", "git diff / git diff --staged in $PAGER"),
+    ("w", "watch for changes on / off"),
+    ("r", "reload"),
+    ("R", "reload, bypassing the git cache and re-reading .fylexignore"),
+    ("< >", "narrow / widen the list pane"),
+    (":", "command palette, e.g. :sort date, :tag+ rust (Tab completes)"),
+    ("?", "this help"),
+    ("q Q Ctrl+C", "quit"),
+];
+// First screen line of the project list
+const LIST_TOP: i32 = 3;
+// Below this draw() only asks for a bigger window
+const MIN_ROWS: i32 = 8;
+const MIN_COLS: i32 = 40;
+
+// Global settings read from ~/.config/fylex/config.toml
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct AppConfig {
+    root: Option<String>,
+    roots: Vec<String>,
+    // Editors that open their own window and shouldn't take over the terminal
+    gui_editors: Vec<String>,
+    // Open projects in tmux sessions even when not already inside tmux
+    tmux: bool,
+    // Share of the width given to the project list, adjustable with < and >
+    list_ratio: f64,
+    // List rows also show the branch and tags, toggled with i
+    expanded_rows: bool,
+    // Directory names skipped when measuring a project's size
+    size_exclude: Vec<String>,
+    // Directory names left out of tarballs (B)
+    tarball_exclude: Vec<String>,
+    // Where tarballs go; next to the project's root when unset
+    tarball_dir: Option<String>,
+    // Directory names not copied when duplicating a project (+)
+    duplicate_exclude: Vec<String>,
+    // Tag -> build command, on top of (and overriding) DEFAULT_BUILD_COMMANDS
+    build_commands: HashMap<String, String>,
+    // Shell command that reads the text to copy on stdin; FYLEX_CLIPBOARD overrides it
+    clipboard: Option<String>,
+    // Reopen with the filter and selection of the previous run
+    restore_session: bool,
+    // Rescan when project folders come and go, and refresh git state on commits;
+    // toggled at runtime with w
+    watch: bool,
+    // Click to select, double click to open, wheel to move. Off by default since
+    // it takes over the terminal's own text selection.
+    mouse: bool,
+    // List symlinked directories in the roots too (marked with ↪)
+    follow_symlinks: bool,
+    // Leave out directories named .something; shown anyway after pressing .
+    hide_dotdirs: bool,
+    // Directory names in the roots that are never listed, e.g. node_modules
+    ignore: Vec<String>,
+    // Seconds between re-checks of the visible projects' git state; 0 turns it off
+    refresh_interval: u64,
+    // Also `git fetch` the visible projects that track an upstream on those
+    // refreshes, each at most every FETCH_INTERVAL. Needs refresh_interval.
+    auto_fetch: bool,
+    // The git executable, for installs outside PATH
+    git: String,
+    // Seconds a git status / branch lookup may take before it's killed and the
+    // project shown as unknown; 0 waits forever
+    git_timeout: u64,
+    // Action -> key, e.g. `delete = "x"`. Actions are the : command names plus
+    // KEYMAP_ONLY; the default keys keep working unless another action takes them.
+    keymap: HashMap<String, String>,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            root: None,
+            roots: Vec::new(),
+            gui_editors: ["code", "codium", "subl", "zed", "gedit", "kate", "gvim", "idea"]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+            tmux: false,
+            list_ratio: 0.40,
+            expanded_rows: false,
+            size_exclude: [".git", "target", "node_modules"]
+                .iter()
+                .map(|d| d.to_string())
+                .collect(),
+            tarball_exclude: [".git", "target", "node_modules"]
+                .iter()
+                .map(|d| d.to_string())
+                .collect(),
+            tarball_dir: None,
+            duplicate_exclude: [".git", "target", "node_modules"]
+                .iter()
+                .map(|d| d.to_string())
+                .collect(),
+            build_commands: HashMap::new(),
+            clipboard: None,
+            restore_session: true,
+            watch: true,
+            mouse: false,
+            follow_symlinks: false,
+            hide_dotdirs: true,
+            ignore: Vec::new(),
+            refresh_interval: 0,
+            auto_fetch: false,
+            git: "git".to_string(),
+            git_timeout: 10,
+            keymap: HashMap::new(),
+        }
+    }
+}
+
+// Every field is optional and unknown ones are ignored, so configs written by
+// older or newer versions keep loading
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ProjectConfig {
+    #[serde(default)]
     name: String,
+    #[serde(default)]
     description: String,
+    #[serde(default)]
     tags: Vec<String>,
+    #[serde(default)]
     created_at: String,
+    // Primary language, shown in the details and matched by the filter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    repo_url: Option<String>,
+    // Project specific actions, run from the ! menu
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    commands: Vec<NamedCommand>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NamedCommand {
+    name: String,
+    // Run through `sh -c` with the project as working directory
+    command: String,
 }
 
 #[derive(Debug, Clone)]
 struct Project {
     path: PathBuf,
+    // Scan root this project was found under
+    root: PathBuf,
+    // Lives in the archive root rather than a scan root
+    archived: bool,
     cfg: Option<ProjectConfig>,
+    // Why the config file couldn't be read, when there is one but it's broken
+    cfg_error: Option<String>,
     // Git status independent from config presence
-    // 1 = clean (V), 2 = modified (M)
+    // 1 = clean (V), 2 = modified (M), 3 = only submodules have changes (S),
+    // 4 = git timed out (?)
     git_state: Option<u8>,
+    // Current branch, or the short hash when HEAD is detached
+    branch: Option<String>,
+    // (ahead, behind) relative to the upstream branch, if one is configured
+    ahead_behind: Option<(u32, u32)>,
+    // Git info is still being computed in the background
+    git_pending: bool,
+    // Directory mtime, for the "modified" sort
+    mtime: Option<std::time::SystemTime>,
+    // Reached through a symlink in the root (follow_symlinks)
+    symlink: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Mode {
+    // Single keys are commands
+    #[default]
+    Normal,
+    // Printable keys edit the filter
+    Filter,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum SortMode {
+    #[default]
+    Name,
+    // Newest created_at first
+    Created,
+    // Modified repos first, then submodule-only changes, then unknown (git timed
+    // out), then clean, then non-git; see dirty_rank
+    Dirty,
+    // Most recently touched directory first
+    Modified,
+    // Most recently opened from fylex first
+    Opened,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Created,
+            SortMode::Created => SortMode::Dirty,
+            SortMode::Dirty => SortMode::Modified,
+            SortMode::Modified => SortMode::Opened,
+            SortMode::Opened => SortMode::Name,
+        }
+    }
+
+    // Inverse of label; "date" and "new" are accepted for created
+    fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "name" => Some(SortMode::Name),
+            "created" | "date" | "new" => Some(SortMode::Created),
+            "dirty" => Some(SortMode::Dirty),
+            "modified" => Some(SortMode::Modified),
+            "opened" => Some(SortMode::Opened),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Created => "created",
+            SortMode::Dirty => "dirty",
+            SortMode::Modified => "modified",
+            SortMode::Opened => "opened",
+        }
+    }
 }
 
 #[derive(Default)]
 struct AppState {
+    config: AppConfig,
+    // First root is where new projects are created
+    roots: Vec<PathBuf>,
+    // Where archived projects are moved to
+    archive_root: PathBuf,
+    show_archived: bool,
+    // . lists the dot directories that hide_dotdirs leaves out
+    show_hidden: bool,
+    // Patterns from .fylexignore, read at startup and again on R
+    ignore_globs: globset::GlobSet,
     projects: Vec<Project>,
     filtered: Vec<usize>,
     selected: usize,
+    // First list_rows line shown in the list
+    scroll_top: usize,
+    // l pins the details pane to this project (index into `projects`) while the
+    // selection moves on; see details_project
+    locked: Option<usize>,
+    // H: the list is split under date headers, see DATE_GROUPS
+    group_by_date: bool,
     filter_text: String,
+    // --read-only: nothing is created, changed or deleted, see MUTATING_KEYS
+    read_only: bool,
+    // Typed key -> the default key of the action it's bound to, from the config's keymap
+    keymap: HashMap<i32, i32>,
+    // # was pressed: the details number the tags and 1-9 picks one
+    tag_pick: bool,
+    // Dates in the details as "2024-03-11 14:02" instead of "2 months ago"
+    absolute_times: bool,
+    // Ctrl+S: match the filter's case exactly instead of ignoring it
+    case_sensitive: bool,
+    // Compiled filter while it starts with re:, or why it doesn't compile
+    filter_regex: Option<Result<regex::Regex, String>>,
+    mode: Mode,
+    sort: SortMode,
+    // Background git results, dropped once every worker is done
+    git_rx: Option<Receiver<GitResult>>,
+    // Projects the running scan started with, for the progress in the status line
+    git_scan_total: usize,
+    // When refresh_visible_git last ran
+    last_refresh: Option<std::time::Instant>,
+    // auto_fetch: fetches still running, and when each project was last fetched
+    fetches: Vec<(PathBuf, Receiver<()>)>,
+    last_fetch: HashMap<PathBuf, std::time::Instant>,
+    // Advances once per main loop pass while background work runs; drives the spinner
+    spinner_frame: usize,
+    git_cache: GitCache,
+    // Project path -> last time it was opened (RFC 3339), see usage_path
+    usage: HashMap<PathBuf, String>,
+    // Pinned project paths, listed before everything else
+    pinned: HashSet<PathBuf>,
+    // README previews keyed by project path
+    readme_cache: HashMap<PathBuf, ReadmePreview>,
+    // Preview scroll offset, only applies while that project is selected
+    readme_scroll: (PathBuf, usize),
+    show_help: bool,
+    // First keybinding shown when the overlay doesn't fit the terminal
+    help_scroll: usize,
+    // Current list/detail split, saved back to the config on exit
+    list_ratio: f64,
+    // Branch and tags inline in the list rows; saved like list_ratio
+    expanded_rows: bool,
+    // Directory sizes keyed by project path, measured on demand for the selection
+    size_cache: HashMap<PathBuf, SizeEntry>,
+    // The measurement in progress, if any
+    size_rx: Option<Receiver<(PathBuf, DirSize)>>,
+    // `git log --oneline` of the selection, valid while .git/index keeps its mtime
+    commits_cache: HashMap<PathBuf, (Option<u64>, Vec<String>)>,
+    // Number of stashes, keyed like commits_cache but on the stash reflog's mtime
+    stash_cache: HashMap<PathBuf, (Option<u64>, usize)>,
+    // First NOTES_LINES of the selection's notes, valid while the file keeps its mtime
+    notes_cache: HashMap<PathBuf, (Option<std::time::SystemTime>, Vec<String>)>,
+    // Background git commands (pull, ...) that haven't reported back yet
+    jobs: Vec<Receiver<JobDone>>,
+    // Projects marked with Space; batch actions apply to these instead of the selection
+    marked: HashSet<PathBuf>,
+    // The terminal has 256 colors, so git states get their own pairs (7-10)
+    rich_colors: bool,
+    // Filesystem watcher, while watch mode is on
+    watch: Option<Watch>,
+    // Changes seen but not acted on yet; handled once `due` passes without new ones
+    watch_pending: WatchPending,
 }
 
-fn scan_projects(root: &str) -> Result<Vec<Project>> {
-    let mut v = Vec::new();
+struct Watch {
+    // Dropping the watcher stops it
+    _watcher: notify::RecommendedWatcher,
+    rx: Receiver<WatchEvent>,
+}
 
-    for entry_res in fs::read_dir(root).with_context(|| format!("Reading directory {}", root))? {
-        let entry = entry_res?;
-        let ty = entry.file_type()?;
-        if !ty.is_dir() {
-            continue;
-        }
-        let path: PathBuf = entry.path();
-        let cfg = read_config(&path).ok().flatten();
-        let git_state = git_status_color(&path).map(|c| c as u8);
-        v.push(Project { path, cfg, git_state });
-    }
+enum WatchEvent {
+    // A folder appeared or went away in one of the roots
+    Roots,
+    // The git index of this project changed
+    Git(PathBuf),
+}
 
-    v.sort_by(|a, b| a.path.file_name().cmp(&b.path.file_name()));
-    Ok(v)
+#[derive(Default)]
+struct WatchPending {
+    due: Option<std::time::Instant>,
+    rescan: bool,
+    git: HashSet<PathBuf>,
 }
 
-fn read_config(dir: &Path) -> Result<Option<ProjectConfig>> {
-    let p = dir.join(CONFIG_NAME);
-    if !p.exists() {
-        return Ok(None);
-    }
-    let mut s = String::new();
-    fs::File::open(&p)?.read_to_string(&mut s)?;
-    let cfg: ProjectConfig = serde_json::from_str(&s)?;
-    Ok(Some(cfg))
+// Outcome of a background git command; Ok carries its output, Err the first error line
+struct JobDone {
+    path: PathBuf,
+    what: &'static str,
+    result: Result<String, String>,
 }
 
-fn git_status_color(path: &Path) -> Option<i32> {
-    if !path.join(".git").exists() {
-        return None;
-    }
+#[derive(Debug, Clone, Copy)]
+struct DirSize {
+    bytes: u64,
+    files: u64,
+}
 
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(path)
-        .arg("status")
-        .arg("--porcelain")
-        .output()
-        .ok()?;
-    if output.stdout.is_empty() {
-        Some(1) // green for clean
-    } else { // oragen for modification
-        Some(2)
-    }
+#[derive(Debug)]
+struct SizeEntry {
+    dir_mtime: Option<std::time::SystemTime>,
+    // None while it's still being measured
+    size: Option<DirSize>,
 }
 
-fn draw(state: &AppState) {
-    erase();
+// Loaded README lines plus the mtimes they were read at
+#[derive(Debug, Default)]
+struct ReadmePreview {
+    dir_mtime: Option<std::time::SystemTime>,
+    file: Option<PathBuf>,
+    file_mtime: Option<std::time::SystemTime>,
+    // None when the project has no README
+    lines: Option<Vec<String>>,
+}
 
-    let mut rows = 0;
-    let mut cols = 0;
-    getmaxyx(stdscr(), &mut rows, &mut cols);
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct GitInfo {
+    state: Option<u8>,
+    branch: Option<String>,
+    ahead_behind: Option<(u32, u32)>,
+}
 
-    attron(COLOR_PAIR(1));
-    mvhline(0, 0, ' ' as u32, cols);
-    let _ = mvprintw(
-        0,
-        1,
-        &format!(
-            " Project Manager - root: {ROOT} | ENTER=open in terminal O=open folder Q=quit A=add cfg T=edit tag N=new R=reload type to filter "
-        ),
-    );
-    attroff(COLOR_PAIR(1));
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
 
-    // ---------- LINHA DE FILTRO ----------
-    attron(COLOR_PAIR(3));
-    let _ = mvprintw(1, 1, "Filter: ");
-    attroff(COLOR_PAIR(3));
-    let _ = mvprintw(1, 9, &state.filter_text);
+// Git info keyed by project path, valid while .git/index keeps the same mtime
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GitCache {
+    version: u32,
+    entries: HashMap<PathBuf, GitCacheEntry>,
+}
 
-    // ---------- LAYOUT DAS DUAS ÁREAS ----------
-    let list_top = 3;
-    let list_width = (cols as f32 * 0.40) as i32;
-    let detail_left = list_width + 2;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GitCacheEntry {
+    // Nanoseconds since the epoch
+    index_mtime: u64,
+    info: GitInfo,
+}
 
-    // ---------- LIST TITLE ----------
-    attron(A_BOLD);
-    let _ = mvprintw(2, 1, "Projects");
-    attroff(A_BOLD);
+struct GitResult {
+    path: PathBuf,
+    info: GitInfo,
+    index_mtime: Option<u64>,
+}
 
-    // ---------- DRAW PROJECT LINES ----------
-    let visible_rows = &state.filtered;
-    for (i, &idx) in visible_rows.iter().enumerate() {
-        if let Some(p) = state.projects.get(idx) {
-            let line = list_top + i as i32;
-            if line >= rows - 1 {
-                break;
-            }
+fn cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".cache")))
+        .map(|d| d.join("fylex"))
+}
 
-            if i == state.selected {
-                attron(COLOR_PAIR(2));
-                mvhline(line, 1, ' ' as u32, list_width - 2);
-                let label =
-                    p.cfg.as_ref().map(|c| c.name.clone()).unwrap_or_else(|| {
-                        p.path.file_name().unwrap().to_string_lossy().to_string()
-                    });
-                let _ = mvprintw(line, 2, &label);
-                attroff(COLOR_PAIR(2));
-                let status = p
-                    .git_state;
-                if let Some(status) = status {
-                    match status {
-                        1 => {
-                            attron(COLOR_PAIR(4));
-                            let _ = mvprintw(line, 2 + label.len() as i32, " | V");
-                            attroff(COLOR_PAIR(4));
-                        },
-                        2 => {
-                            attron(COLOR_PAIR(4));
-                            let _ = mvprintw(line, 2 + label.len() as i32, " | M");
-                            attroff(COLOR_PAIR(5));
-                        }
-                        _ => {}
-                    }
-                }
-            } else {
-                let label =
-                    p.cfg.as_ref().map(|c| c.name.clone()).unwrap_or_else(|| {
-                        p.path.file_name().unwrap().to_string_lossy().to_string()
-                    });
-                let _ = mvprintw(line, 2, &label);
-                let status = p
-                    .git_state;
-                if let Some(status) = status {
-                    match status {
-                        1 => {
-                            // Use initialized green pair for clean git state
-                            attron(COLOR_PAIR(4));
-                            let _ = mvprintw(line, 2 + label.len() as i32, " | V");
-                            attroff(COLOR_PAIR(4));
-                        },
-                        2 => {
-                            attron(COLOR_PAIR(4));
-                            let _ = mvprintw(line, 2 + label.len() as i32, " | M");
-                            attroff(COLOR_PAIR(4));
-                        }
-                        _ => {}
-                    }
-                }
-            }
+fn data_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".local").join("share")))
+        .map(|d| d.join("fylex"))
+}
+
+fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".config")))
+        .map(|d| d.join("fylex"))
+}
+
+fn expand_tilde(s: &str) -> PathBuf {
+    if let Some(home) = home_dir() {
+        if s == "~" {
+            return home;
+        }
+        if let Some(rest) = s.strip_prefix("~/") {
+            return home.join(rest);
         }
     }
+    PathBuf::from(s)
+}
 
-    // ---------- DETAIL AREA ----------
-    attron(A_BOLD);
-    let _ = mvprintw(2, detail_left, "Details");
-    attroff(A_BOLD);
-
-    if let Some(p) = current_project(state) {
-        let mut y = 3;
+fn app_config_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("config.toml"))
+}
 
-        // -- Name --
-        let label_name = p
-            .cfg
-            .as_ref()
-            .map(|c| c.name.as_str())
-            .unwrap_or("(No config file set)");
-        attron(COLOR_PAIR(3));
-        let _ = mvprintw(y, detail_left, "Name: ");
-        attroff(COLOR_PAIR(3));
-        let _ = mvprintw(y, detail_left + 6, label_name);
-        y += 1;
+fn read_app_config() -> Result<AppConfig> {
+    let Some(p) = app_config_path() else {
+        return Ok(AppConfig::default());
+    };
+    if !p.exists() {
+        return Ok(AppConfig::default());
+    }
+    let s = fs::read_to_string(&p).with_context(|| format!("Reading {}", p.display()))?;
+    toml::from_str(&s).with_context(|| format!("Parsing {}", p.display()))
+}
 
-        // -- Path --
-        attron(COLOR_PAIR(3));
-        let _ = mvprintw(y, detail_left, "Path: ");
-        attroff(COLOR_PAIR(3));
-        let _ = mvprintw(y, detail_left + 6, &p.path.to_string_lossy());
-        y += 1;
+fn tilde_path(p: &Path) -> String {
+    if let Some(home) = home_dir()
+        && let Ok(rest) = p.strip_prefix(&home)
+    {
+        if rest.as_os_str().is_empty() {
+            return "~".to_string();
+        }
+        return format!("~/{}", rest.display());
+    }
+    p.display().to_string()
+}
 
-        // -- Tags --
-        let tags_str = p
-            .cfg
-            .as_ref()
-            .map(|c| c.tags.join(", "))
-            .unwrap_or_default();
-        attron(COLOR_PAIR(3));
-        let _ = mvprintw(y, detail_left, "Tags: ");
-        attroff(COLOR_PAIR(3));
-        let _ = mvprintw(y, detail_left + 6, &tags_str);
-        y += 1;
+// Update a single top-level key in config.toml, keeping the rest of the file (comments
+// included) as the user wrote it
+fn save_app_setting(key: &str, value: toml_edit::Value) -> Result<()> {
+    let p = app_config_path().context("No config directory")?;
+    let text = if p.exists() {
+        fs::read_to_string(&p).with_context(|| format!("Reading {}", p.display()))?
+    } else {
+        String::new()
+    };
+    let mut doc: toml_edit::DocumentMut = text
+        .parse()
+        .with_context(|| format!("Parsing {}", p.display()))?;
+    doc[key] = toml_edit::value(value);
+    if let Some(dir) = p.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&p, doc.to_string()).with_context(|| format!("Writing {}", p.display()))?;
+    Ok(())
+}
 
-        // -- Description --
-        let desc = p
-            .cfg
-            .as_ref()
-            .map(|c| c.description.clone())
-            .unwrap_or_default();
-        attron(COLOR_PAIR(3));
-        let _ = mvprintw(y, detail_left, "Description: ");
-        attroff(COLOR_PAIR(3));
+// FYLEX_ROOT (colon separated) wins over the config file, which wins over the default
+fn resolve_roots(cfg: &AppConfig) -> Vec<PathBuf> {
+    let raw: Vec<String> = match std::env::var("FYLEX_ROOT") {
+        Ok(r) if !r.trim().is_empty() => r.split(':').map(|s| s.to_string()).collect(),
+        _ => cfg.root.iter().chain(cfg.roots.iter()).cloned().collect(),
+    };
+    let mut roots: Vec<PathBuf> = Vec::new();
+    for r in raw.iter().map(|r| r.trim()).filter(|r| !r.is_empty()) {
+        let p = expand_tilde(r);
+        if !roots.contains(&p) {
+            roots.push(p);
+        }
+    }
+    if roots.is_empty() {
+        roots.push(expand_tilde(DEFAULT_ROOT));
+    }
+    roots
+}
 
-        wrap_print(
-            y,
-            detail_left + 6,
-            desc.as_str(),
-            (cols - detail_left - 2) as usize,
-            rows - y - 1,
-        );
+// $FYLEX_ARCHIVE, or an "archive" folder next to the first root
+fn resolve_archive_root(roots: &[PathBuf]) -> PathBuf {
+    if let Ok(a) = std::env::var("FYLEX_ARCHIVE")
+        && !a.trim().is_empty()
+    {
+        return expand_tilde(a.trim());
     }
+    let first = &roots[0];
+    first.parent().unwrap_or(first).join("archive")
+}
 
-    refresh();
+fn load_project(path: PathBuf, root: &Path, archived: bool, symlink: bool) -> Project {
+    let (cfg, cfg_error) = match read_config(&path) {
+        Ok(cfg) => (cfg, None),
+        Err(e) => (None, Some(format!("{e:#}"))),
+    };
+    // Git details are filled in later by spawn_git_scan
+    let git_pending = path.join(".git").exists();
+    let mtime = fs::metadata(&path).and_then(|m| m.modified()).ok();
+    Project {
+        path,
+        root: root.to_path_buf(),
+        archived,
+        cfg,
+        cfg_error,
+        git_state: None,
+        branch: None,
+        ahead_behind: None,
+        git_pending,
+        mtime,
+        symlink,
+    }
 }
 
-fn wrap_print(mut y: i32, x: i32, text: &str, width: usize, max_lines: i32) {
-    let mut line = String::new();
-    let mut used = 0;
+// What a scan lists besides plain directories
+struct ScanOptions<'a> {
+    follow_symlinks: bool,
+    show_hidden: bool,
+    ignore: &'a [String],
+    ignore_globs: &'a globset::GlobSet,
+}
 
-    for word in text.split_whitespace() {
-        if line.len() + 1 + word.len() > width && !line.is_empty() {
-            if used >= max_lines {
-                break;
-            }
-            let _ = mvprintw(y, x, &line);
-            y += 1;
-            used += 1;
-            line.clear();
-        }
-        if !line.is_empty() {
-            line.push(' ');
+impl<'a> ScanOptions<'a> {
+    fn new(config: &'a AppConfig, show_hidden: bool, ignore_globs: &'a globset::GlobSet) -> Self {
+        ScanOptions {
+            follow_symlinks: config.follow_symlinks,
+            show_hidden: show_hidden || !config.hide_dotdirs,
+            ignore: &config.ignore,
+            ignore_globs,
         }
-        line.push_str(word);
     }
-    if !line.is_empty() && used < max_lines {
-        let _ = mvprintw(y, x, &line);
+
+    fn skips(&self, name: &str) -> bool {
+        (!self.show_hidden && name.starts_with('.'))
+            || self.ignore.iter().any(|i| i == name)
+            || self.ignore_globs.is_match(name)
     }
 }
 
-fn current_project(state: &AppState) -> Option<&Project> {
-    state
-        .filtered
-        .get(state.selected)
-        .and_then(|&i| state.projects.get(i))
+fn ignore_file_path() -> Option<PathBuf> {
+    config_dir().map(|d| d.join(".fylexignore"))
 }
 
-fn rebuild_filter(state: &mut AppState) {
-    let f = state.filter_text.to_lowercase();
-    state.filtered.clear();
+// ~/.config/fylex/.fylexignore holds one glob per line (`*-backup`, `archive-*`),
+// matched against the names of the directories in the roots; # starts a comment.
+// A missing file ignores nothing.
+fn load_ignore_file() -> (globset::GlobSet, Vec<String>) {
+    match ignore_file_path().and_then(|p| fs::read_to_string(p).ok()) {
+        Some(text) => parse_ignore(&text),
+        None => (globset::GlobSet::empty(), Vec::new()),
+    }
+}
 
-    // if f.is_empty() {
-    //     state.selected = 0;
-    //     return;
-    // }
+// Bad patterns are left out and come back as warnings
+fn parse_ignore(text: &str) -> (globset::GlobSet, Vec<String>) {
+    let mut builder = globset::GlobSetBuilder::new();
+    let mut warnings = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let pattern = line.trim();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            continue;
+        }
+        if pattern.starts_with('!') {
+            warnings.push(format!(".fylexignore line {}: ! patterns aren't supported", n + 1));
+            continue;
+        }
+        // Only directories directly in a root are matched, so /name and name/ mean name
+        match globset::Glob::new(pattern.trim_matches('/')) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => warnings.push(format!(".fylexignore line {}: {e}", n + 1)),
+        }
+    }
+    let set = builder.build().unwrap_or_else(|e| {
+        warnings.push(format!(".fylexignore ignored: {e}"));
+        globset::GlobSet::empty()
+    });
+    (set, warnings)
+}
 
-    for (i, p) in state.projects.iter().enumerate() {
-        let name = p
-            .cfg
-            .as_ref()
-            .map(|c| c.name.to_lowercase())
-            .unwrap_or_else(|| p.path.file_name().unwrap().to_string_lossy().to_lowercase());
+// A project directory seen in a root; its config is read by load_projects
+struct FoundDir {
+    path: PathBuf,
+    root: PathBuf,
+    archived: bool,
+    symlink: bool,
+}
 
-        let tags = p
-            .cfg
-            .as_ref()
-            .map(|c| {
-                c.tags
-                    .iter()
-                    .map(|t| t.to_lowercase())
-                    .collect::<Vec<_>>()
-                    .join(",")
+// Reading the configs is most of a scan on a slow or network disk, and the time
+// goes into waiting on I/O, so this uses more threads than there are cores.
+// 3000 configured directories, one core: 370ms -> 210ms from a cold page cache,
+// about the same (50-60ms) from a warm one.
+const LOAD_THREADS: usize = 8;
+
+// In `found` order; scan_projects sorts the result anyway
+fn load_projects(found: Vec<FoundDir>) -> Vec<Project> {
+    let chunk = found.len().div_ceil(LOAD_THREADS).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = found
+            .chunks(chunk)
+            .map(|dirs| {
+                s.spawn(move || {
+                    dirs.iter()
+                        .map(|d| load_project(d.path.clone(), &d.root, d.archived, d.symlink))
+                        .collect::<Vec<_>>()
+                })
             })
-            .unwrap_or_default();
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("config loader panicked"))
+            .collect()
+    })
+}
 
-        let hay = format!("{name} {tags}");
-        if hay.contains(&f) {
-            state.filtered.push(i);
+// A symlink seen in a root, resolved once every root has been scanned
+struct RootLink {
+    path: PathBuf,
+    root: PathBuf,
+    archived: bool,
+}
+
+// Only an unreadable root is an error; entries that can't be read are skipped and
+// counted in the returned number. Symlinks are collected into `links` when given.
+fn scan_root(
+    root: &Path,
+    archived: bool,
+    v: &mut Vec<FoundDir>,
+    mut links: Option<&mut Vec<RootLink>>,
+    opts: &ScanOptions,
+) -> Result<usize> {
+    let mut unreadable = 0;
+    let entries =
+        fs::read_dir(root).with_context(|| format!("Reading directory {}", root.display()))?;
+    for entry_res in entries {
+        let Ok(entry) = entry_res else {
+            unreadable += 1;
+            continue;
+        };
+        let Ok(ty) = entry.file_type() else {
+            unreadable += 1;
+            continue;
+        };
+        if opts.skips(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        if ty.is_symlink()
+            && let Some(links) = links.as_deref_mut()
+        {
+            links.push(RootLink { path: entry.path(), root: root.to_path_buf(), archived });
+            continue;
         }
+        if !ty.is_dir() {
+            continue;
+        }
+        v.push(FoundDir {
+            path: entry.path(),
+            root: root.to_path_buf(),
+            archived,
+            symlink: false,
+        });
     }
-    if state.selected >= state.filtered.len() {
-        state.selected = state.filtered.len().saturating_sub(1);
+    Ok(unreadable)
+}
+
+// Add the symlinked directories that lead somewhere not listed yet. Links to a
+// project (or root) that's already there, including loops back into a root, are
+// dropped, as are dangling ones.
+fn follow_links(links: Vec<RootLink>, roots: &[PathBuf], v: &mut Vec<FoundDir>) {
+    let mut visited: HashSet<PathBuf> = roots
+        .iter()
+        .chain(v.iter().map(|p| &p.path))
+        .filter_map(|p| fs::canonicalize(p).ok())
+        .collect();
+    for link in links {
+        let Ok(target) = fs::canonicalize(&link.path) else {
+            continue;
+        };
+        if target.is_dir() && visited.insert(target) {
+            v.push(FoundDir {
+                path: link.path,
+                root: link.root,
+                archived: link.archived,
+                symlink: true,
+            });
+        }
     }
 }
 
-fn open_in_terminal(path: &Path) -> Result<()> {
-    // Restore terminal before handing control to the user's shell
-    endwin();
+// Unreadable roots are skipped and reported back; it's only an error when none can be read.
+// The archive root is only listed when given, and is fine to be missing.
+fn scan_projects(
+    roots: &[PathBuf],
+    archive: Option<&Path>,
+    opts: &ScanOptions,
+) -> Result<(Vec<Project>, Vec<String>)> {
+    let mut v = Vec::new();
+    let mut skipped = Vec::new();
+    let mut failed = 0;
+    let mut last_err = None;
 
-    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string());
+    let mut unreadable = 0;
+    let mut links = Vec::new();
+    let mut links_out = opts.follow_symlinks.then_some(&mut links);
 
-    #[cfg(unix)]
+    if let Some(archive) = archive
+        && archive.is_dir()
     {
-        use std::os::unix::process::CommandExt;
-        // Clear screen before handing off to the shell (exec never returns on success)
-        print!("\x1B[2J\x1B[H");
-        let _ = std::io::stdout().flush();
-        let err = Command::new(&shell).current_dir(path).exec();
-        // If exec returns, it failed
-        Err(anyhow::anyhow!(format!("exec failed: {err}")))
+        match scan_root(archive, true, &mut v, links_out.as_deref_mut(), opts) {
+            Ok(n) => unreadable += n,
+            Err(e) => skipped.push(format!("Skipped archive {}: {e:#}", tilde_path(archive))),
+        }
     }
-
-    #[cfg(not(unix))]
+    for root in roots {
+        match scan_root(root, false, &mut v, links_out.as_deref_mut(), opts) {
+            Ok(n) => unreadable += n,
+            Err(e) => {
+                skipped.push(format!("Skipped {}: {e:#}", tilde_path(root)));
+                failed += 1;
+                last_err = Some(e);
+            }
+        }
+    }
+    if opts.follow_symlinks {
+        follow_links(links, roots, &mut v);
+    }
+    match unreadable {
+        0 => {}
+        1 => skipped.push("Skipped 1 unreadable entry".to_string()),
+        n => skipped.push(format!("Skipped {n} unreadable entries")),
+    }
+    if let Some(e) = last_err
+        && failed == roots.len()
     {
-        // Fallback: run and wait, then exit with the same code
-        let status = Command::new(&shell).current_dir(path).status()?;
-        process::exit(status.code().unwrap_or(0));
+        return Err(e);
     }
+
+    let mut v = load_projects(v);
+    v.sort_by(|a, b| {
+        a.path
+            .file_name()
+            .cmp(&b.path.file_name())
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    Ok((v, skipped))
 }
 
-fn flash_line(msg: &str, color_pair: i16) {
-    let mut rows = 0;
-    let mut cols = 0;
-    getmaxyx(stdscr(), &mut rows, &mut cols);
+fn read_config(dir: &Path) -> Result<Option<ProjectConfig>> {
+    let p = dir.join(CONFIG_NAME);
+    if !p.exists() {
+        return Ok(None);
+    }
+    let mut s = String::new();
+    fs::File::open(&p)?.read_to_string(&mut s)?;
+    let cfg: ProjectConfig =
+        serde_json::from_str(&s).with_context(|| format!("Parsing {CONFIG_NAME}"))?;
+    Ok(Some(cfg))
+}
+
+// The repository's git directory: `.git` itself, or where a `.git` file points
+// (worktrees and submodules use a `gitdir: <path>` file)
+fn git_dir(path: &Path) -> Option<PathBuf> {
+    let dot_git = path.join(".git");
+    let meta = fs::metadata(&dot_git).ok()?;
+    if meta.is_dir() {
+        return Some(dot_git);
+    }
+    let text = fs::read_to_string(&dot_git).ok()?;
+    let target = text.lines().find_map(|l| l.strip_prefix("gitdir:"))?.trim();
+    Some(path.join(target))
+}
+
+// Where and how long to run git. Set once from the config in main, since git
+// also runs on scan threads that have no AppState.
+struct GitSettings {
+    program: String,
+    timeout: Option<std::time::Duration>,
+}
+
+static GIT_SETTINGS: std::sync::OnceLock<GitSettings> = std::sync::OnceLock::new();
+
+fn git_command() -> Command {
+    Command::new(GIT_SETTINGS.get().map_or("git", |g| g.program.as_str()))
+}
+
+// `cmd.output()`, but a command that outlives the configured git timeout is killed
+// and reported as ErrorKind::TimedOut
+fn output_with_timeout(cmd: &mut Command) -> std::io::Result<std::process::Output> {
+    let Some(timeout) = GIT_SETTINGS.get().and_then(|g| g.timeout) else {
+        return cmd.output();
+    };
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drain both pipes on the side so a chatty command can't block on a full one
+    fn read_all(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    }
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+    let deadline = std::time::Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if std::time::Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("no answer after {}s", timeout.as_secs()),
+            ));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    };
+    Ok(std::process::Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn git_status_color(path: &Path) -> Option<i32> {
+    if !path.join(".git").exists() {
+        return None;
+    }
+
+    // v2 marks submodule entries, so their changes can be told apart
+    let output = match output_with_timeout(
+        git_command()
+            .arg("-C")
+            .arg(path)
+            .args(["status", "--porcelain=v2", "--ignore-submodules=none"]),
+    ) {
+        Ok(output) => output,
+        // Hung, e.g. on a stale network mount
+        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => return Some(4),
+        Err(_) => return None,
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut submodule_changes = false;
+    for line in stdout.lines() {
+        // "1 XY <sub> ..." / "2 XY <sub> ..." where <sub> is S... for a submodule
+        let is_submodule = matches!(line.split(' ').nth(2), Some(sub) if sub.starts_with('S'))
+            && (line.starts_with("1 ") || line.starts_with("2 "));
+        if is_submodule {
+            submodule_changes = true;
+        } else {
+            return Some(2); // orange for modification
+        }
+    }
+    if submodule_changes {
+        Some(3)
+    } else {
+        Some(1) // green for clean
+    }
+}
+
+fn git_output(path: &Path, args: &[&str]) -> Option<String> {
+    let output = output_with_timeout(git_command().arg("-C").arg(path).args(args)).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Empty for repos without commits (or when git fails)
+fn recent_commits(path: &Path, n: usize) -> Vec<String> {
+    git_output(path, &["log", "--oneline", "--no-color", "-n", &n.to_string()])
+        .map(|out| out.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn refresh_commits(state: &mut AppState) {
+    let Some(p) = details_project(state).filter(|p| p.git_state.is_some()) else {
+        return;
+    };
+    let stamp = git_index_mtime(&p.path);
+    if state.commits_cache.get(&p.path).is_some_and(|(s, _)| *s == stamp) {
+        return;
+    }
+    let commits = recent_commits(&p.path, RECENT_COMMITS);
+    let path = p.path.clone();
+    state.commits_cache.insert(path, (stamp, commits));
+}
+
+fn stash_count(path: &Path) -> usize {
+    git_output(path, &["stash", "list"]).map_or(0, |out| out.lines().count())
+}
+
+// Only the selection is counted. Every stash push / pop / drop rewrites the stash
+// reflog, so its mtime tells when the count is stale.
+fn refresh_stash(state: &mut AppState) {
+    let Some(p) = details_project(state).filter(|p| p.git_state.is_some()) else {
+        return;
+    };
+    let stamp = git_file_mtime(&p.path, "logs/refs/stash");
+    if state.stash_cache.get(&p.path).is_some_and(|(s, _)| *s == stamp) {
+        return;
+    }
+    // No reflog, no stashes
+    let count = if stamp.is_some() { stash_count(&p.path) } else { 0 };
+    let path = p.path.clone();
+    state.stash_cache.insert(path, (stamp, count));
+}
+
+// Only the selection is read; saving the notes in the editor bumps the mtime, so
+// the preview catches up on return
+fn refresh_notes(state: &mut AppState) {
+    let Some(p) = details_project(state) else {
+        return;
+    };
+    let file = p.path.join(NOTES_NAME);
+    let stamp = mtime_of(&file);
+    if state.notes_cache.get(&p.path).is_some_and(|(s, _)| *s == stamp) {
+        return;
+    }
+    let lines = fs::read_to_string(&file)
+        .map(|text| {
+            text.lines()
+                .skip_while(|l| l.trim().is_empty())
+                .take(NOTES_LINES)
+                .map(|l| l.trim_end().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    let path = p.path.clone();
+    state.notes_cache.insert(path, (stamp, lines));
+}
+
+fn git_branch(path: &Path) -> Option<String> {
+    match git_output(path, &["rev-parse", "--abbrev-ref", "HEAD"]).as_deref() {
+        // Detached HEAD
+        Some("HEAD") => git_output(path, &["rev-parse", "--short", "HEAD"]),
+        Some(b) => Some(b.to_string()),
+        // No commits yet, but HEAD still names the branch
+        None => git_output(path, &["symbolic-ref", "--short", "HEAD"]),
+    }
+}
+
+// Fails (None) when the branch has no upstream
+fn git_ahead_behind(path: &Path) -> Option<(u32, u32)> {
+    let out = git_output(path, &["rev-list", "--left-right", "--count", "@{upstream}...HEAD"])?;
+    let mut nums = out.split_whitespace().map(|n| n.parse::<u32>());
+    let behind = nums.next()?.ok()?;
+    let ahead = nums.next()?.ok()?;
+    Some((ahead, behind))
+}
+
+fn format_ahead_behind(ahead: u32, behind: u32) -> String {
+    format!("↑{ahead} ↓{behind}")
+}
+
+fn git_info(path: &Path) -> GitInfo {
+    let state = git_status_color(path).map(|c| c as u8);
+    // Don't wait out the timeout again for the details of a repository that timed out
+    let known = state.filter(|&s| s != 4);
+    GitInfo {
+        state,
+        branch: known.and_then(|_| git_branch(path)),
+        ahead_behind: known.and_then(|_| git_ahead_behind(path)),
+    }
+}
+
+fn git_index_mtime(path: &Path) -> Option<u64> {
+    git_file_mtime(path, "index")
+}
+
+// Mtime in nanoseconds of a file inside the repository's git directory
+fn git_file_mtime(path: &Path, file: &str) -> Option<u64> {
+    let mtime = fs::metadata(git_dir(path)?.join(file))
+        .and_then(|m| m.modified())
+        .ok()?;
+    let since_epoch = mtime.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_nanos() as u64)
+}
+
+fn git_cache_path() -> Option<PathBuf> {
+    cache_dir().map(|d| d.join("gitcache.json"))
+}
+
+// A missing, unreadable or outdated cache just starts empty
+fn load_git_cache() -> GitCache {
+    git_cache_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str::<GitCache>(&s).ok())
+        .filter(|c| c.version == GIT_CACHE_VERSION)
+        .unwrap_or_default()
+}
+
+fn save_git_cache(cache: &GitCache) -> Result<()> {
+    let Some(p) = git_cache_path() else {
+        return Ok(());
+    };
+    if let Some(dir) = p.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let out = GitCache {
+        version: GIT_CACHE_VERSION,
+        entries: cache.entries.clone(),
+    };
+    fs::write(p, serde_json::to_string(&out)?)?;
+    Ok(())
+}
+
+fn apply_git_info(p: &mut Project, info: GitInfo) {
+    p.git_state = info.state;
+    p.branch = info.branch;
+    p.ahead_behind = info.ahead_behind;
+    p.git_pending = false;
+}
+
+// Compute git info on a small worker pool; results arrive over the returned channel.
+// Entries in `cached` whose index mtime still matches skip running git. Note that
+// unstaged edits don't touch the index, which is why a forced refresh exists.
+fn spawn_git_scan(
+    mut paths: Vec<PathBuf>,
+    cached: HashMap<PathBuf, GitCacheEntry>,
+) -> Receiver<GitResult> {
+    let (tx, rx) = mpsc::channel();
+    // Workers pop from the back, so reverse to resolve the top of the list first
+    paths.reverse();
+    let queue = Arc::new(Mutex::new(paths));
+    let cached = Arc::new(cached);
+    let workers = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .min(8);
+    for _ in 0..workers {
+        let tx = tx.clone();
+        let queue = Arc::clone(&queue);
+        let cached = Arc::clone(&cached);
+        std::thread::spawn(move || {
+            loop {
+                let next = queue.lock().unwrap().pop();
+                let Some(path) = next else {
+                    break;
+                };
+                let index_mtime = git_index_mtime(&path);
+                let info = match cached.get(&path) {
+                    Some(e) if Some(e.index_mtime) == index_mtime => e.info.clone(),
+                    _ => git_info(&path),
+                };
+                if tx.send(GitResult { path, info, index_mtime }).is_err() {
+                    // Receiver was replaced by a newer scan
+                    break;
+                }
+            }
+        });
+    }
+    rx
+}
+
+// `force` ignores the on-disk cache and asks git for everything
+fn start_git_scan(state: &mut AppState, force: bool) {
+    let paths: Vec<PathBuf> = state
+        .projects
+        .iter()
+        .filter(|p| p.git_pending)
+        .map(|p| p.path.clone())
+        .collect();
+    state.git_scan_total = paths.len();
+    // A full scan counts as a refresh for refresh_visible_git
+    state.last_refresh = Some(std::time::Instant::now());
+    let cached = if force {
+        HashMap::new()
+    } else {
+        state.git_cache.entries.clone()
+    };
+    state.git_rx = Some(spawn_git_scan(paths, cached));
+}
+
+// Re-check the git state of the rows on screen, bypassing the cache so unstaged
+// edits show up. Runs every refresh_interval seconds, skipped while a scan is going.
+fn refresh_visible_git(state: &mut AppState) {
+    let Some(interval) = refresh_interval(&state.config) else {
+        return;
+    };
+    if state.last_refresh.is_some_and(|t| t.elapsed() < interval) || state.git_rx.is_some() {
+        return;
+    }
+    state.last_refresh = Some(std::time::Instant::now());
+    let paths: Vec<PathBuf> = list_rows(state)
+        .into_iter()
+        .skip(state.scroll_top)
+        .take(list_height())
+        .filter_map(|row| match row {
+            ListRow::Project(pos) => Some(&state.projects[state.filtered[pos]]),
+            ListRow::Header(_) => None,
+        })
+        .filter(|p| p.git_state.is_some())
+        .map(|p| p.path.clone())
+        .collect();
+    if state.config.auto_fetch {
+        fetch_stale(state, &paths);
+    }
+    if paths.is_empty() {
+        return;
+    }
+    state.git_scan_total = 0;
+    state.git_rx = Some(spawn_git_scan(paths, HashMap::new()));
+}
+
+// How often auto_fetch fetches the same project
+const FETCH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(600);
+
+// Start a background `git fetch` for those of `paths` that track an upstream and
+// weren't fetched in the last FETCH_INTERVAL. Failures (no network, auth) are
+// ignored; the project is simply tried again later.
+fn fetch_stale(state: &mut AppState, paths: &[PathBuf]) {
+    for path in paths {
+        let tracked = state
+            .projects
+            .iter()
+            .any(|p| &p.path == path && p.ahead_behind.is_some());
+        let running = state.fetches.iter().any(|(p, _)| p == path);
+        let recent = state.last_fetch.get(path).is_some_and(|t| t.elapsed() < FETCH_INTERVAL);
+        if !tracked || running || recent {
+            continue;
+        }
+        let mut cmd = git_job_command(path, &["fetch", "--quiet"]);
+        cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = cmd.status();
+            let _ = tx.send(());
+        });
+        state.last_fetch.insert(path.clone(), std::time::Instant::now());
+        state.fetches.push((path.clone(), rx));
+    }
+}
+
+// Drop finished fetches. The next refresh comes right away so the new
+// ahead/behind counts show up.
+fn poll_fetches(state: &mut AppState) {
+    let before = state.fetches.len();
+    state
+        .fetches
+        .retain(|(_, rx)| matches!(rx.try_recv(), Err(mpsc::TryRecvError::Empty)));
+    if state.fetches.len() < before {
+        state.last_refresh = None;
+    }
+}
+
+fn refresh_interval(config: &AppConfig) -> Option<std::time::Duration> {
+    (config.refresh_interval > 0).then(|| std::time::Duration::from_secs(config.refresh_interval))
+}
+
+// git in `path` for a background job: it must fail rather than ask for credentials
+fn git_job_command(path: &Path, args: &[&str]) -> Command {
+    let mut cmd = git_command();
+    cmd.arg("-C")
+        .arg(path)
+        .args(args)
+        .stdin(Stdio::null())
+        .env("GIT_TERMINAL_PROMPT", "0");
+    if std::env::var_os("GIT_SSH_COMMAND").is_none() {
+        cmd.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+    }
+    cmd
+}
+
+// Run `git -C path args` on a thread. It must never stop to ask for credentials,
+// since the terminal belongs to the TUI, so prompting is switched off.
+fn spawn_git_job(state: &mut AppState, path: &Path, what: &'static str, args: &[&str]) {
+    let mut cmd = git_job_command(path, args);
+    let (tx, rx) = mpsc::channel();
+    let path = path.to_path_buf();
+    std::thread::spawn(move || {
+        let result = match cmd.output() {
+            Ok(out) if out.status.success() => {
+                Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+            }
+            Ok(out) => {
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                Err(stderr
+                    .lines()
+                    .find(|l| !l.trim().is_empty())
+                    .unwrap_or("exited with an error")
+                    .to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        let _ = tx.send(JobDone { path, what, result });
+    });
+    state.jobs.push(rx);
+}
+
+// Report finished jobs and refresh the git state of the projects they touched
+fn poll_jobs(state: &mut AppState) {
+    let mut done = Vec::new();
+    state.jobs.retain(|rx| match rx.try_recv() {
+        Ok(job) => {
+            done.push(job);
+            false
+        }
+        Err(TryRecvError::Empty) => true,
+        Err(TryRecvError::Disconnected) => false,
+    });
+    for job in done {
+        let name = state
+            .projects
+            .iter_mut()
+            .find(|p| p.path == job.path)
+            .map(|p| {
+                apply_git_info(p, git_info(&job.path));
+                display_name(p)
+            })
+            .unwrap_or_else(|| dir_name(&job.path));
+        match job.result {
+            Ok(out) => {
+                let summary = out.lines().last().unwrap_or("done");
+                flash_ok(&format!("{} {name}: {summary}", job.what));
+            }
+            Err(e) => flash_error(&format!("{} {name} failed: {e}", job.what)),
+        }
+    }
+}
+
+fn batch_summary(ok: usize, failed: usize, verb: &str) -> String {
+    format!("{ok} {verb}, {failed} failed")
+}
+
+// Run `action` on every marked project, then clear the marks and flash a summary.
+// The first error is shown as well, since the summary alone doesn't say what went wrong.
+fn run_batch(
+    state: &mut AppState,
+    verb: &str,
+    mut action: impl FnMut(&mut AppState, usize) -> Result<()>,
+) {
+    let indices: Vec<usize> = state
+        .projects
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| state.marked.contains(&p.path))
+        .map(|(i, _)| i)
+        .collect();
+    let mut failed = 0;
+    let mut first_err = None;
+    for &i in &indices {
+        if let Err(e) = action(state, i) {
+            failed += 1;
+            first_err.get_or_insert_with(|| format!("{}: {e:#}", display_name(&state.projects[i])));
+        }
+    }
+    state.marked.clear();
+    rebuild_filter(state);
+    let summary = batch_summary(indices.len() - failed, failed, verb);
+    match first_err {
+        Some(e) => flash_error(&format!("{summary} ({e})")),
+        None => flash_ok(&summary),
+    }
+}
+
+// Fast-forward only, and only from a clean tree, so a pull never starts a merge
+fn pull_project(state: &mut AppState, p: &Project) -> Result<()> {
+    // The cached state misses unstaged edits, so ask git again
+    match git_status_color(&p.path) {
+        None => return Err(anyhow::anyhow!("Not a git repository")),
+        Some(1) => {}
+        Some(4) => return Err(anyhow::anyhow!("git status timed out, not pulling")),
+        Some(_) => {
+            return Err(anyhow::anyhow!("Working tree has changes, commit or stash first"));
+        }
+    }
+    spawn_git_job(state, &p.path, "Pull", &["pull", "--ff-only"]);
+    Ok(())
+}
+
+fn stash_project(state: &mut AppState, p: &Project) -> Result<()> {
+    // git itself says "No local changes to save"; the cached state may be stale
+    if p.git_state.is_none() {
+        return Err(anyhow::anyhow!("Not a git repository"));
+    }
+    spawn_git_job(state, &p.path, "Stash", &["stash", "push"]);
+    Ok(())
+}
+
+fn stash_pop_project(state: &mut AppState, p: &Project) -> Result<()> {
+    if p.git_state.is_none() {
+        return Err(anyhow::anyhow!("Not a git repository"));
+    }
+    if stash_count(&p.path) == 0 {
+        return Err(anyhow::anyhow!("No stashes to pop"));
+    }
+    spawn_git_job(state, &p.path, "Stash pop", &["stash", "pop"]);
+    Ok(())
+}
+
+// `git commit -am`; returns git's summary line. Untracked files are left alone.
+fn commit_project(p: &mut Project, message: &str) -> Result<String> {
+    // The cached state may be stale, so a clean tree is left to git to report
+    if p.git_state.is_none() {
+        return Err(anyhow::anyhow!("Not a git repository"));
+    }
+    let out = git_command()
+        .arg("-C")
+        .arg(&p.path)
+        .args(["commit", "-am", message])
+        .stdin(Stdio::null())
+        .output()
+        .context("Running git")?;
+    apply_git_info(p, git_info(&p.path));
+    if !out.status.success() {
+        // Hooks complain on stderr; "nothing added to commit" ends git's stdout instead
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let line = stderr
+            .lines()
+            .find(|l| !l.trim().is_empty())
+            .or_else(|| stdout.lines().rfind(|l| !l.trim().is_empty()))
+            .unwrap_or("git commit failed");
+        return Err(anyhow::anyhow!("{}", line.trim()));
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    Ok(stdout.lines().next().unwrap_or("Committed").trim().to_string())
+}
+
+// Drain finished git results without blocking
+fn poll_git_updates(state: &mut AppState) {
+    let Some(rx) = &state.git_rx else {
+        return;
+    };
+    let mut changed = false;
+    loop {
+        match rx.try_recv() {
+            Ok(res) => {
+                match res.index_mtime {
+                    // A timed out status is worth asking again next time
+                    Some(index_mtime) if res.info.state != Some(4) => {
+                        let entry = GitCacheEntry {
+                            index_mtime,
+                            info: res.info.clone(),
+                        };
+                        state.git_cache.entries.insert(res.path.clone(), entry);
+                    }
+                    _ => {
+                        state.git_cache.entries.remove(&res.path);
+                    }
+                }
+                if let Some(p) = state.projects.iter_mut().find(|p| p.path == res.path) {
+                    apply_git_info(p, res.info);
+                    changed = true;
+                }
+            }
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => {
+                state.git_rx = None;
+                // Best effort: a cache that can't be written just means running git next time
+                let _ = save_git_cache(&state.git_cache);
+                break;
+            }
+        }
+    }
+    // Only the dirty sort depends on git state
+    if changed && state.sort == SortMode::Dirty {
+        let selected_path = current_project(state).map(|p| p.path.clone());
+        rebuild_filter(state);
+        if let Some(path) = selected_path {
+            select_path(state, &path);
+        }
+    }
+}
+
+fn draw(state: &AppState) {
+    erase();
+
+    let mut rows = 0;
+    let mut cols = 0;
+    getmaxyx(stdscr(), &mut rows, &mut cols);
+    if rows < MIN_ROWS || cols < MIN_COLS {
+        draw_too_small(rows, cols);
+        refresh();
+        return;
+    }
+    // Both panes end at the status line, right above the footer
+    let status_row = rows - 2;
+
+    attron(COLOR_PAIR(1));
+    mvhline(0, 0, ' ' as u32, cols);
+    let header = format!(
+        " Project Manager - root: {} | ?=help q=quit ",
+        state
+            .roots
+            .iter()
+            .map(|r| tilde_path(r))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let _ = mvprintw(0, 1, &fit(&header, cols - 2));
+    attroff(COLOR_PAIR(1));
+
+    // ---------- LINHA DE FILTRO ----------
+    attron(COLOR_PAIR(3));
+    let _ = mvprintw(1, 1, "Filter: ");
+    attroff(COLOR_PAIR(3));
+    let mode_label = match state.mode {
+        Mode::Normal => "NORMAL",
+        Mode::Filter => "-- FILTER --",
+    };
+    let _ = mvprintw(1, 9, &fit(&state.filter_text, cols - 11 - mode_label.len() as i32));
+    attron(A_BOLD);
+    let _ = mvprintw(1, cols - mode_label.len() as i32 - 1, mode_label);
+    attroff(A_BOLD);
+
+    // ---------- LAYOUT DAS DUAS ÁREAS ----------
+    let list_top = LIST_TOP;
+    let list_width = list_width(state, cols);
+    let detail_left = list_width + 2;
+
+    // ---------- LIST TITLE ----------
+    attron(A_BOLD);
+    let _ = mvprintw(2, 1, "Projects");
+    attroff(A_BOLD);
+
+    // ---------- DRAW PROJECT LINES ----------
+    let height = list_height();
+    let list = list_rows(state);
+    let visible_rows = list.iter().enumerate().skip(state.scroll_top).take(height);
+    for (i, row) in visible_rows {
+        let line = list_top + (i - state.scroll_top) as i32;
+        match *row {
+            ListRow::Header(group) => draw_group_header(line, DATE_GROUPS[group], list_width),
+            ListRow::Project(pos) => {
+                if let Some(p) = state.filtered.get(pos).and_then(|&i| state.projects.get(i)) {
+                    draw_project_row(state, p, line, pos == state.selected, list_width);
+                }
+            }
+        }
+    }
+
+    // ---------- SCROLL INDICATORS ----------
+    // Headers don't count, only projects
+    let projects_in = |rows: &[ListRow]| {
+        rows.iter().filter(|r| matches!(r, ListRow::Project(_))).count()
+    };
+    let above = projects_in(&list[..state.scroll_top.min(list.len())]);
+    if above > 0 {
+        let msg = format!("▲ {above} more");
+        let _ = mvprintw(2, list_width - msg.chars().count() as i32 - 1, &msg);
+    }
+    let below = projects_in(&list[(state.scroll_top + height).min(list.len())..]);
+    if below > 0 {
+        let msg = format!("▼ {below} more");
+        let _ = mvprintw(status_row, list_width - msg.chars().count() as i32 - 1, &msg);
+    }
+
+    // ---------- DETAIL AREA ----------
+    attron(A_BOLD);
+    let _ = mvprintw(2, detail_left, "Details");
+    attroff(A_BOLD);
+    if state.locked.is_some() {
+        attron(COLOR_PAIR(4));
+        let _ = addstr(" (locked)");
+        attroff(COLOR_PAIR(4));
+    }
+
+    if let Some(p) = details_project(state) {
+        let mut y = 3;
+
+        // -- Name --
+        let label_name = p
+            .cfg
+            .as_ref()
+            .map(|c| c.name.as_str())
+            .unwrap_or("(No config file set)");
+        attron(COLOR_PAIR(3));
+        let _ = mvprintw(y, detail_left, "Name: ");
+        attroff(COLOR_PAIR(3));
+        print_clipped(y, detail_left + 6, label_name, cols);
+        y += 1;
+
+        // -- Broken config --
+        if let Some(err) = &p.cfg_error {
+            attron(COLOR_PAIR(5));
+            let _ = mvprintw(y, detail_left, &fit(&format!("! {err}"), cols - detail_left - 1));
+            attroff(COLOR_PAIR(5));
+            y += 1;
+        }
+
+        // -- Path --
+        attron(COLOR_PAIR(3));
+        let _ = mvprintw(y, detail_left, "Path: ");
+        attroff(COLOR_PAIR(3));
+        print_clipped(y, detail_left + 6, &p.path.to_string_lossy(), cols);
+        y += 1;
+
+        // -- Root --
+        attron(COLOR_PAIR(3));
+        let _ = mvprintw(y, detail_left, "Root: ");
+        attroff(COLOR_PAIR(3));
+        print_clipped(y, detail_left + 6, &tilde_path(&p.root), cols);
+        y += 1;
+
+        // -- Size --
+        if let Some(entry) = state.size_cache.get(&p.path) {
+            attron(COLOR_PAIR(3));
+            let _ = mvprintw(y, detail_left, "Size: ");
+            attroff(COLOR_PAIR(3));
+            match entry.size {
+                Some(size) => {
+                    let plural = if size.files == 1 { "" } else { "s" };
+                    let text =
+                        format!("{} ({} file{plural})", human_size(size.bytes), size.files);
+                    print_clipped(y, detail_left + 6, &text, cols);
+                }
+                None => {
+                    attron(A_DIM);
+                    print_clipped(y, detail_left + 6, "counting…", cols);
+                    attroff(A_DIM);
+                }
+            }
+            y += 1;
+        }
+
+        // -- Branch --
+        if let Some(branch) = &p.branch {
+            attron(COLOR_PAIR(3));
+            let _ = mvprintw(y, detail_left, "Branch: ");
+            attroff(COLOR_PAIR(3));
+            print_clipped(y, detail_left + 8, branch, cols);
+            y += 1;
+        }
+
+        // -- Ahead / behind --
+        if let Some((ahead, behind)) = p.ahead_behind {
+            attron(COLOR_PAIR(3));
+            let _ = mvprintw(y, detail_left, "Sync: ");
+            attroff(COLOR_PAIR(3));
+            print_clipped(y, detail_left + 6, &format_ahead_behind(ahead, behind), cols);
+            y += 1;
+        }
+
+        // -- Stashes --
+        if let Some(&(_, count)) = state.stash_cache.get(&p.path)
+            && count > 0
+        {
+            attron(COLOR_PAIR(3));
+            let _ = mvprintw(y, detail_left, "Stashes: ");
+            attroff(COLOR_PAIR(3));
+            print_clipped(y, detail_left + 9, &count.to_string(), cols);
+            y += 1;
+        }
+
+        // -- Language / repository --
+        if let Some(cfg) = &p.cfg {
+            if let Some(language) = &cfg.language {
+                attron(COLOR_PAIR(3));
+                let _ = mvprintw(y, detail_left, "Language: ");
+                attroff(COLOR_PAIR(3));
+                print_clipped(y, detail_left + 10, language, cols);
+                y += 1;
+            }
+            if let Some(url) = &cfg.repo_url {
+                attron(COLOR_PAIR(3));
+                let _ = mvprintw(y, detail_left, "Repo: ");
+                attroff(COLOR_PAIR(3));
+                print_clipped(y, detail_left + 6, url, cols);
+                y += 1;
+            }
+        }
+
+        // -- Last opened --
+        let opened = last_opened(&state.usage, p)
+            .map(|t| format_time(state, &t))
+            .unwrap_or_else(|| "never".to_string());
+        attron(COLOR_PAIR(3));
+        let _ = mvprintw(y, detail_left, "Last opened: ");
+        attroff(COLOR_PAIR(3));
+        print_clipped(y, detail_left + 13, &opened, cols);
+        y += 1;
+
+        // -- Created --
+        if p.cfg.is_some() {
+            let created = created_at(p)
+                .map(|t| format_time(state, &t))
+                .unwrap_or_else(|| "unknown".to_string());
+            attron(COLOR_PAIR(3));
+            let _ = mvprintw(y, detail_left, "Created: ");
+            attroff(COLOR_PAIR(3));
+            print_clipped(y, detail_left + 9, &created, cols);
+            y += 1;
+        }
+
+        // -- Tags --
+        let tags_str = p
+            .cfg
+            .as_ref()
+            .map(|c| {
+                if state.tag_pick {
+                    let numbered: Vec<String> = c
+                        .tags
+                        .iter()
+                        .take(9)
+                        .enumerate()
+                        .map(|(i, t)| format!("{}:{t}", i + 1))
+                        .collect();
+                    numbered.join(" ")
+                } else {
+                    c.tags.join(", ")
+                }
+            })
+            .unwrap_or_default();
+        attron(COLOR_PAIR(3));
+        let _ = mvprintw(y, detail_left, "Tags: ");
+        attroff(COLOR_PAIR(3));
+        print_clipped(y, detail_left + 6, &tags_str, cols);
+        y += 1;
+
+        // -- Description --
+        let desc = p
+            .cfg
+            .as_ref()
+            .map(|c| c.description.clone())
+            .unwrap_or_default();
+        attron(COLOR_PAIR(3));
+        let _ = mvprintw(y, detail_left, "Description: ");
+        attroff(COLOR_PAIR(3));
+
+        let used = wrap_print(
+            y,
+            detail_left + 13,
+            desc.as_str(),
+            (cols - detail_left - 15).max(0) as usize,
+            status_row - y,
+        );
+        y += used.max(1) + 1;
+
+        // -- Notes --
+        if let Some((_, notes)) = state.notes_cache.get(&p.path)
+            && !notes.is_empty()
+            && y < status_row
+        {
+            attron(A_BOLD);
+            let _ = mvprintw(y, detail_left, "Notes");
+            attroff(A_BOLD);
+            y += 1;
+            for line in notes {
+                if y >= status_row {
+                    break;
+                }
+                let _ = mvprintw(y, detail_left, &fit(line, cols - detail_left - 1));
+                y += 1;
+            }
+            y += 1;
+        }
+
+        // -- Recent commits --
+        if let Some((_, commits)) = state.commits_cache.get(&p.path)
+            && p.git_state.is_some()
+            && y < status_row
+        {
+            attron(A_BOLD);
+            let _ = mvprintw(y, detail_left, "Recent");
+            attroff(A_BOLD);
+            y += 1;
+            if commits.is_empty() && y < status_row {
+                attron(A_DIM);
+                let _ = mvprintw(y, detail_left, "(no commits yet)");
+                attroff(A_DIM);
+                y += 1;
+            }
+            for commit in commits {
+                if y >= status_row {
+                    break;
+                }
+                let _ = mvprintw(y, detail_left, &fit(commit, cols - detail_left - 1));
+                y += 1;
+            }
+            y += 1;
+        }
+
+        // -- README preview --
+        if y < status_row {
+            attron(A_BOLD);
+            let _ = mvprintw(y, detail_left, "README");
+            attroff(A_BOLD);
+            y += 1;
+            let lines = state
+                .readme_cache
+                .get(&p.path)
+                .and_then(|r| r.lines.as_ref());
+            match lines {
+                Some(lines) => {
+                    let offset = if state.readme_scroll.0 == p.path {
+                        state.readme_scroll.1
+                    } else {
+                        0
+                    };
+                    for line in lines.iter().skip(offset) {
+                        if y >= status_row {
+                            break;
+                        }
+                        let used = wrap_print(
+                            y,
+                            detail_left,
+                            line,
+                            (cols - detail_left - 2).max(0) as usize,
+                            status_row - y,
+                        );
+                        y += used.max(1);
+                    }
+                }
+                None => {
+                    let _ = mvprintw(y, detail_left, "(no README)");
+                }
+            }
+        }
+    }
+
+    draw_status(state);
+    draw_footer(state);
+
+    if state.show_help {
+        draw_help(rows, cols, &keymap_help(&state.keymap), state.help_scroll);
+    }
+
+    refresh();
+}
+
+// Print at (y, x) without running past the right edge; a long value would wrap
+// onto the next line and scramble the layout
+fn print_clipped(y: i32, x: i32, text: &str, cols: i32) {
+    let _ = mvprintw(y, x, &fit(text, cols - x - 1));
+}
+
+fn draw_group_header(line: i32, label: &str, list_width: i32) {
+    attron(COLOR_PAIR(3) | A_BOLD);
+    mvhline(line, 1, ACS_HLINE(), (list_width - 2).max(0));
+    let _ = mvprintw(line, 3, &fit(&format!(" {label} "), list_width - 5));
+    attroff(COLOR_PAIR(3) | A_BOLD);
+}
+
+// Stands in for the whole layout while the window is smaller than MIN_ROWS x MIN_COLS
+fn draw_too_small(rows: i32, cols: i32) {
+    let lines = [
+        "Terminal too small".to_string(),
+        format!("{cols}x{rows}, need {MIN_COLS}x{MIN_ROWS}"),
+    ];
+    let top = ((rows - lines.len() as i32) / 2).max(0);
+    for (i, line) in lines.iter().enumerate() {
+        let line = fit(line, cols);
+        let x = ((cols - line.chars().count() as i32) / 2).max(0);
+        let _ = mvprintw(top + i as i32, x, &line);
+    }
+}
+
+// Char positions in `label` that the filter matched, so the row can show why it's
+// listed. Only the name part is searched; it always ends the label, before any
+// " [archived]" suffix.
+fn label_highlight(state: &AppState, p: &Project, label: &str) -> Vec<usize> {
+    let name = display_name(p);
+    let core = label.strip_suffix(" [archived]").unwrap_or(label);
+    if state.filter_text.is_empty() || !core.ends_with(&name) {
+        return Vec::new();
+    }
+    let offset = core.chars().count() - name.chars().count();
+    if let Some(re) = &state.filter_regex {
+        let Ok(re) = re else {
+            return Vec::new();
+        };
+        // Byte ranges of the matches, turned into char positions
+        let mut positions = Vec::new();
+        for m in re.find_iter(&name) {
+            let start = name[..m.start()].chars().count();
+            let len = m.as_str().chars().count();
+            positions.extend((start..start + len).map(|i| i + offset));
+        }
+        return positions;
+    }
+    // Lowercase char by char so positions line up with the label
+    let hay: Vec<char> = if state.case_sensitive {
+        name.chars().collect()
+    } else {
+        name.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect()
+    };
+    let mut positions = Vec::new();
+    for tok in parse_filter(&filter_case(&state.filter_text, state.case_sensitive)) {
+        if let FilterToken::Name(v) | FilterToken::Any(v) = tok {
+            positions.extend(match_positions(&hay, &v).into_iter().map(|i| i + offset));
+        }
+    }
+    positions
+}
+
+// Where `needle` matches in `hay`, following fuzzy_score: the first substring
+// occurrence if there is one, else the greedy subsequence
+fn match_positions(hay: &[char], needle: &str) -> Vec<usize> {
+    let needle: Vec<char> = needle.chars().collect();
+    if needle.is_empty() || needle.len() > hay.len() {
+        return Vec::new();
+    }
+    if let Some(start) = hay.windows(needle.len()).position(|w| w == needle.as_slice()) {
+        return (start..start + needle.len()).collect();
+    }
+    let mut positions = Vec::new();
+    let mut start = 0;
+    for nc in needle {
+        let Some(pos) = hay[start..].iter().position(|&c| c == nc) else {
+            return Vec::new();
+        };
+        positions.push(start + pos);
+        start += pos + 1;
+    }
+    positions
+}
+
+// Print `text` with the chars at `highlight` underlined and bold, on top of
+// whatever attributes are already on
+fn print_highlighted(y: i32, x: i32, text: &str, highlight: &[usize]) {
+    mv(y, x);
+    for (i, c) in text.chars().enumerate() {
+        let on = highlight.contains(&i);
+        if on {
+            attron(A_UNDERLINE | A_BOLD);
+        }
+        let _ = addstr(c.encode_utf8(&mut [0; 4]));
+        if on {
+            attroff(A_UNDERLINE | A_BOLD);
+        }
+    }
+}
+
+// One entry of the project list: the label, then its git state and sync counts
+fn draw_project_row(state: &AppState, p: &Project, line: i32, selected: bool, list_width: i32) {
+    let label = list_label(state, p);
+    let label = if state.marked.contains(&p.path) {
+        format!("✓ {label}")
+    } else {
+        label
+    };
+    let highlight = label_highlight(state, p, &label);
+    if selected {
+        attron(COLOR_PAIR(2));
+        mvhline(line, 1, ' ' as u32, (list_width - 2).max(0));
+        print_highlighted(line, 2, &label, &highlight);
+        attroff(COLOR_PAIR(2));
+    } else {
+        // Dim the folders that still need a config
+        let unconfigured = p.cfg.is_none();
+        if unconfigured {
+            attron(COLOR_PAIR(6) | A_DIM);
+        }
+        print_highlighted(line, 2, &label, &highlight);
+        if unconfigured {
+            attroff(COLOR_PAIR(6) | A_DIM);
+        }
+    }
+
+    let mut x = 2 + label.chars().count() as i32;
+    if p.symlink {
+        attron(A_DIM);
+        let _ = mvprintw(line, x, " ↪");
+        attroff(A_DIM);
+        x += 2;
+    }
+    if is_new(p) {
+        attron(COLOR_PAIR(4) | A_BOLD);
+        let _ = mvprintw(line, x, " NEW");
+        attroff(COLOR_PAIR(4) | A_BOLD);
+        x += 4;
+    }
+    // The config exists but couldn't be parsed
+    if p.cfg_error.is_some() {
+        attron(COLOR_PAIR(5) | A_BOLD);
+        let _ = mvprintw(line, x, " !");
+        attroff(COLOR_PAIR(5) | A_BOLD);
+        x += 2;
+    }
+    // Pairs 7-10 only exist on 256-color terminals; otherwise reuse ok/warn and
+    // leave the sync counts uncolored
+    let (clean, modified, ahead_attr, behind_attr) = if state.rich_colors {
+        (COLOR_PAIR(7), COLOR_PAIR(8), COLOR_PAIR(9), COLOR_PAIR(10))
+    } else {
+        (COLOR_PAIR(4), COLOR_PAIR(5), 0, 0)
+    };
+    if let Some(status) = p.git_state {
+        match status {
+            1 => {
+                attron(clean);
+                let _ = mvprintw(line, x, " | V");
+                attroff(clean);
+            }
+            2 => {
+                attron(modified);
+                let _ = mvprintw(line, x, " | M");
+                attroff(modified);
+            }
+            3 => {
+                attron(modified);
+                let _ = mvprintw(line, x, " | S");
+                attroff(modified);
+            }
+            // git timed out
+            4 => {
+                attron(A_DIM);
+                let _ = mvprintw(line, x, " | ?");
+                attroff(A_DIM);
+            }
+            _ => {}
+        }
+        // Only when out of sync with upstream
+        if let Some((ahead, behind)) = p.ahead_behind.filter(|&ab| ab != (0, 0)) {
+            attron(ahead_attr);
+            let _ = mvprintw(line, x + 4, &format!(" ↑{ahead}"));
+            attroff(ahead_attr);
+            attron(behind_attr);
+            let _ = addstr(&format!(" ↓{behind}"));
+            attroff(behind_attr);
+        }
+        // Known only for projects that have been selected
+        if let Some(&(_, count)) = state.stash_cache.get(&p.path)
+            && count > 0
+        {
+            attron(A_DIM);
+            let _ = addstr(&format!(" ≡{count}"));
+            attroff(A_DIM);
+        }
+        // auto_fetch is fetching it in the background
+        if state.fetches.iter().any(|(path, _)| path == &p.path) {
+            attron(A_DIM);
+            let _ = addstr(" ⇣");
+            attroff(A_DIM);
+        }
+    } else if p.git_pending {
+        attron(A_DIM);
+        let _ = mvprintw(line, x, " scanning…");
+        attroff(A_DIM);
+    }
+
+    if state.expanded_rows {
+        draw_row_details(p, line, list_width);
+    }
+}
+
+// Expanded rows: the branch and tags after the indicators, cut off with an
+// ellipsis at the list pane's edge
+fn draw_row_details(p: &Project, line: i32, list_width: i32) {
+    let mut text = String::new();
+    if let Some(branch) = &p.branch {
+        text.push_str(&format!("  {branch}"));
+    }
+    if let Some(cfg) = p.cfg.as_ref().filter(|c| !c.tags.is_empty()) {
+        text.push_str(&format!("  {}", cfg.tags.join(", ")));
+    }
+    let x = getcurx(stdscr());
+    let room = list_width - 1 - x;
+    if text.is_empty() || room < 2 {
+        return;
+    }
+    let text = if text.chars().count() as i32 > room {
+        format!("{}…", fit(&text, room - 1))
+    } else {
+        text
+    };
+    attron(A_DIM);
+    let _ = mvprintw(line, x, &text);
+    attroff(A_DIM);
+}
+
+// Frames for the background work indicator in the status line
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+// Bottom line under the detail pane: match counts, sort order and the active filter
+fn draw_status(state: &AppState) {
+    let mut rows = 0;
+    let mut cols = 0;
+    getmaxyx(stdscr(), &mut rows, &mut cols);
+    let left = list_width(state, cols) + 2;
+
+    let mut status = format!(
+        "{}/{} shown | sort: {}",
+        state.filtered.len(),
+        state.projects.len(),
+        state.sort.label()
+    );
+    if state.group_by_date {
+        status.push_str(" | grouped by date");
+    }
+    if !state.marked.is_empty() {
+        status.push_str(&format!(" | {} marked", state.marked.len()));
+    }
+    // What's left to onboard, see is:nocfg
+    let unconfigured = state.projects.iter().filter(|p| is_unconfigured(p)).count();
+    if unconfigured > 0 {
+        status.push_str(&format!(" | {unconfigured} without config"));
+    }
+    if !state.filter_text.is_empty() {
+        status.push_str(&format!(" | filter: {}", state.filter_text));
+    }
+    if let Some(Err(e)) = &state.filter_regex {
+        status.push_str(&format!(" | bad regex: {e}"));
+    } else if state.filter_regex.is_none() {
+        let tokens = parse_filter(&filter_case(&state.filter_text, state.case_sensitive));
+        for tok in tokens {
+            if let FilterToken::Is(v) = tok
+                && !v.is_empty()
+                && !IS_VALUES.contains(&v.as_str())
+            {
+                status.push_str(&format!(" | is: takes {}", IS_VALUES.join(", ")));
+                break;
+            }
+        }
+    }
+    if state.case_sensitive {
+        status.push_str(" | case-sensitive");
+    }
+    if state.read_only {
+        status.push_str(" | read-only");
+    }
+    // Background refreshes (refresh_interval) don't count, they start with a total of 0
+    if state.git_rx.is_some() && state.git_scan_total > 0 {
+        let pending = state.projects.iter().filter(|p| p.git_pending).count();
+        let done = state.git_scan_total.saturating_sub(pending);
+        let frame = SPINNER[state.spinner_frame % SPINNER.len()];
+        status.push_str(&format!(" | {frame} git {done}/{}", state.git_scan_total));
+    }
+    attron(A_DIM);
+    let _ = mvprintw(rows - 2, left, &fit(&status, cols - left - 1));
+    attroff(A_DIM);
+}
+
+// Last line: the root of the selection and the time. Flashes and prompts take the
+// line over while they're up.
+fn draw_footer(state: &AppState) {
+    let mut rows = 0;
+    let mut cols = 0;
+    getmaxyx(stdscr(), &mut rows, &mut cols);
+    let root = match current_project(state) {
+        Some(p) => tilde_path(&p.root),
+        None => state.roots.iter().map(|r| tilde_path(r)).collect::<Vec<_>>().join(", "),
+    };
+    let clock = chrono::Local::now().format("%H:%M").to_string();
+    attron(COLOR_PAIR(1));
+    mvhline(rows - 1, 0, ' ' as u32, cols);
+    let _ = mvprintw(rows - 1, 1, &fit(&format!("root: {root}"), cols - clock.len() as i32 - 3));
+    let _ = mvprintw(rows - 1, cols - clock.len() as i32 - 1, &clock);
+    attroff(COLOR_PAIR(1));
+}
+
+// Milliseconds until the footer clock turns over to the next minute
+fn ms_to_next_minute() -> i32 {
+    use chrono::Timelike;
+    let now = chrono::Local::now();
+    let into_minute = now.second() * 1000 + now.timestamp_subsec_millis().min(999);
+    (60_000 - into_minute) as i32
+}
+
+// Truncate to at most `width` characters
+fn fit(s: &str, width: i32) -> String {
+    s.chars().take(width.max(0) as usize).collect()
+}
+
+// Blank, bordered box centered on the screen; returns its top-left corner
+fn draw_box(rows: i32, cols: i32, h: i32, w: i32, title: &str) -> (i32, i32) {
+    let top = (rows - h) / 2;
+    let left = (cols - w) / 2;
+
+    for y in top..top + h {
+        mvhline(y, left, ' ' as u32, w);
+    }
+    mvhline(top, left, ACS_HLINE(), w);
+    mvhline(top + h - 1, left, ACS_HLINE(), w);
+    mvvline(top, left, ACS_VLINE(), h);
+    mvvline(top, left + w - 1, ACS_VLINE(), h);
+    mvaddch(top, left, ACS_ULCORNER());
+    mvaddch(top, left + w - 1, ACS_URCORNER());
+    mvaddch(top + h - 1, left, ACS_LLCORNER());
+    mvaddch(top + h - 1, left + w - 1, ACS_LRCORNER());
+
+    attron(A_BOLD);
+    let _ = mvprintw(top, left + 2, &fit(title, w - 4));
+    attroff(A_BOLD);
+    (top, left)
+}
+
+// Let the user pick one of `items` (j/k or arrows and Enter, or its number);
+// None when cancelled with Esc or q
+fn pick_from_menu(title: &str, items: &[String]) -> Option<usize> {
+    let mut selected = 0;
+    loop {
+        let mut rows = 0;
+        let mut cols = 0;
+        getmaxyx(stdscr(), &mut rows, &mut cols);
+        let item_w = items.iter().map(|i| i.chars().count()).max().unwrap_or(0);
+        let w = ((item_w + 8).max(title.chars().count() + 4) as i32).min(cols);
+        let h = (items.len() as i32 + 4).min(rows);
+        let (top, left) = draw_box(rows, cols, h, w, title);
+        for (i, item) in items.iter().enumerate() {
+            let y = top + 2 + i as i32;
+            if y >= top + h - 1 {
+                break;
+            }
+            let text = fit(&format!("{} {item}", i + 1), w - 4);
+            if i == selected {
+                attron(COLOR_PAIR(2));
+                let _ = mvprintw(y, left + 2, &text);
+                attroff(COLOR_PAIR(2));
+            } else {
+                let _ = mvprintw(y, left + 2, &text);
+            }
+        }
+        refresh();
+        match getch() {
+            10 | KEY_ENTER => return Some(selected),
+            27 | 113 => return None,
+            KEY_UP | 107 => selected = selected.saturating_sub(1),
+            KEY_DOWN | 106 => selected = (selected + 1).min(items.len().saturating_sub(1)),
+            c @ 49..=57 if ((c - 49) as usize) < items.len() => return Some((c - 49) as usize),
+            _ => {}
+        }
+    }
+}
+
+// Centered box over whatever draw() rendered; the next draw() repaints everything,
+// so closing it leaves nothing behind
+// Keybindings the help overlay has room for on a terminal `rows` high
+fn help_page(rows: i32, total: usize) -> usize {
+    (rows - 3).clamp(1, total.max(1) as i32) as usize
+}
+
+fn draw_help(rows: i32, cols: i32, remapped: &[(String, String)], scroll: usize) {
+    let lines: Vec<(&str, &str)> = KEYBINDINGS
+        .iter()
+        .copied()
+        .chain(remapped.iter().map(|(k, d)| (k.as_str(), d.as_str())))
+        .collect();
+    let key_w = lines.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    let desc_w = lines.iter().map(|(_, d)| d.len()).max().unwrap_or(0);
+    let w = ((key_w + desc_w + 7) as i32).min(cols);
+    let h = (lines.len() as i32 + 4).min(rows);
+    let page = help_page(rows, lines.len());
+    let scroll = scroll.min(lines.len() - page);
+    // Say which part is shown when they don't all fit
+    let title = if page < lines.len() {
+        format!(
+            " Keys {}-{} of {} (j/k to scroll) ",
+            scroll + 1,
+            scroll + page,
+            lines.len()
+        )
+    } else {
+        " Keys (any key to close) ".to_string()
+    };
+    let (top, left) = draw_box(rows, cols, h, w, &title);
+    for (i, (key, desc)) in lines.iter().skip(scroll).take(page).enumerate() {
+        let y = top + 2 + i as i32;
+        attron(COLOR_PAIR(3));
+        let _ = mvprintw(y, left + 2, &fit(key, w - 4));
+        attroff(COLOR_PAIR(3));
+        let desc_x = left + 3 + key_w as i32;
+        let _ = mvprintw(y, desc_x, &fit(desc, left + w - 2 - desc_x));
+    }
+    if scroll + page < lines.len() {
+        let _ = mvprintw(top + h - 1, left + 2, &fit(" more… ", w - 4));
+    }
+}
+
+// Returns how many screen lines were printed
+fn wrap_print(mut y: i32, x: i32, text: &str, width: usize, max_lines: i32) -> i32 {
+    let mut line = String::new();
+    let mut used = 0;
+
+    for word in text.split_whitespace() {
+        if line.chars().count() + 1 + word.chars().count() > width && !line.is_empty() {
+            if used >= max_lines {
+                break;
+            }
+            let _ = mvprintw(y, x, &line);
+            y += 1;
+            used += 1;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() && used < max_lines {
+        let _ = mvprintw(y, x, &line);
+        used += 1;
+    }
+    used
+}
+
+fn mtime_of(p: &Path) -> Option<std::time::SystemTime> {
+    fs::metadata(p).and_then(|m| m.modified()).ok()
+}
+
+// Total size and file count under `dir`, skipping `exclude`d directory names.
+// Symlinks are counted as themselves, never followed; unreadable entries are skipped.
+fn dir_size(dir: &Path, exclude: &[String]) -> DirSize {
+    let mut total = DirSize { bytes: 0, files: 0 };
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(d) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&d) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                if !exclude.iter().any(|x| entry.file_name() == x.as_str()) {
+                    stack.push(entry.path());
+                }
+            } else {
+                total.bytes += meta.len();
+                total.files += 1;
+            }
+        }
+    }
+    total
+}
+
+// <dir>/<name>-<date>.tar.gz, numbered when that's taken
+fn tarball_path(config: &AppConfig, p: &Project) -> PathBuf {
+    let dir = match &config.tarball_dir {
+        Some(d) => expand_tilde(d),
+        None => p.root.parent().unwrap_or(&p.root).to_path_buf(),
+    };
+    let stem = format!("{}-{}", dir_name(&p.path), chrono::Local::now().format("%Y-%m-%d"));
+    let mut out = dir.join(format!("{stem}.tar.gz"));
+    let mut n = 2;
+    while out.exists() {
+        out = dir.join(format!("{stem}-{n}.tar.gz"));
+        n += 1;
+    }
+    out
+}
+
+// Pack the project into a gzipped tarball on a background thread; the result comes
+// back as a job. A failed run doesn't leave a partial file behind.
+fn spawn_tarball(state: &mut AppState, p: &Project) {
+    let out = tarball_path(&state.config, p);
+    let exclude = state.config.tarball_exclude.clone();
+    let path = p.path.clone();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let result = write_tarball(&path, &out, &exclude)
+            .map(|_| tilde_path(&out))
+            .map_err(|e| {
+                let _ = fs::remove_file(&out);
+                format!("{e:#}")
+            });
+        let _ = tx.send(JobDone { path, what: "Tarball", result });
+    });
+    state.jobs.push(rx);
+}
+
+fn write_tarball(dir: &Path, out: &Path, exclude: &[String]) -> Result<()> {
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = fs::File::create(out).with_context(|| format!("Creating {}", out.display()))?;
+    let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut tar = tar::Builder::new(gz);
+    // Symlinks are stored as links, not followed
+    tar.follow_symlinks(false);
+    let top = PathBuf::from(dir_name(dir));
+    tar.append_dir(&top, dir)?;
+    let mut stack = vec![(dir.to_path_buf(), top)];
+    while let Some((d, in_tar)) = stack.pop() {
+        let entries = fs::read_dir(&d).with_context(|| format!("Reading {}", d.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let path = entry.path();
+            let target = in_tar.join(&name);
+            if entry.file_type()?.is_dir() {
+                if exclude.iter().any(|x| name == x.as_str()) {
+                    continue;
+                }
+                tar.append_dir(&target, &path)?;
+                stack.push((path, target));
+            } else {
+                tar.append_path_with_name(&path, &target)
+                    .with_context(|| format!("Adding {}", path.display()))?;
+            }
+        }
+    }
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+// Start measuring the selected project unless a measurement for its current mtime
+// exists; runs on a thread so large trees don't stall scrolling
+fn refresh_size(state: &mut AppState) {
+    let Some(dir) = details_project(state).map(|p| p.path.clone()) else {
+        return;
+    };
+    let dir_mtime = mtime_of(&dir);
+    if state.size_cache.get(&dir).is_some_and(|e| e.dir_mtime == dir_mtime) {
+        return;
+    }
+    // Only one measurement runs at a time; forget the one being replaced
+    state.size_cache.retain(|_, e| e.size.is_some());
+    state.size_cache.insert(dir.clone(), SizeEntry { dir_mtime, size: None });
+    let exclude = state.config.size_exclude.clone();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let size = dir_size(&dir, &exclude);
+        let _ = tx.send((dir, size));
+    });
+    state.size_rx = Some(rx);
+}
+
+// How long the filesystem has to stay quiet before acting on watched changes
+const WATCH_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
+// Watch the roots themselves (not recursively) and every project's git directory,
+// where commits, checkouts and staging all rewrite the index
+fn start_watch(state: &mut AppState) -> Result<()> {
+    use notify::{EventKind, RecursiveMode, Watcher, event::ModifyKind};
+
+    let roots: HashSet<PathBuf> = state.roots.iter().cloned().collect();
+    let git_dirs: HashMap<PathBuf, PathBuf> = state
+        .projects
+        .iter()
+        .filter_map(|p| git_dir(&p.path).map(|d| (d, p.path.clone())))
+        .collect();
+    let (tx, rx) = mpsc::channel();
+    let roots_seen = roots.clone();
+    let git_seen = git_dirs.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else {
+            return;
+        };
+        let structural = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+        );
+        for path in &event.paths {
+            let Some(parent) = path.parent() else {
+                continue;
+            };
+            if structural && roots_seen.contains(parent) {
+                let _ = tx.send(WatchEvent::Roots);
+            } else if path.file_name().is_some_and(|n| n == "index")
+                && let Some(project) = git_seen.get(parent)
+            {
+                let _ = tx.send(WatchEvent::Git(project.clone()));
+            }
+        }
+    })?;
+    for root in &roots {
+        watcher
+            .watch(root, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Watching {}", root.display()))?;
+    }
+    for dir in git_dirs.keys() {
+        // A repo that can't be watched just misses live updates
+        let _ = watcher.watch(dir, RecursiveMode::NonRecursive);
+    }
+    state.watch = Some(Watch { _watcher: watcher, rx });
+    Ok(())
+}
+
+// Collect watch events, then rescan or refresh git once things settle down
+fn poll_watch(state: &mut AppState) {
+    let Some(watch) = &state.watch else {
+        return;
+    };
+    let now = std::time::Instant::now();
+    while let Ok(event) = watch.rx.try_recv() {
+        match event {
+            WatchEvent::Roots => state.watch_pending.rescan = true,
+            WatchEvent::Git(path) => {
+                state.watch_pending.git.insert(path);
+            }
+        }
+        state.watch_pending.due = Some(now + WATCH_DEBOUNCE);
+    }
+    if state.watch_pending.due.is_none_or(|due| due > now) {
+        return;
+    }
+    let pending = std::mem::take(&mut state.watch_pending);
+    if pending.rescan {
+        if let Err(e) = reload_projects(state, false) {
+            flash_error(&format!("Reload failed: {e:#}"));
+        }
+        return;
+    }
+    for p in state.projects.iter_mut().filter(|p| pending.git.contains(&p.path)) {
+        apply_git_info(p, git_info(&p.path));
+    }
+    if state.sort == SortMode::Dirty {
+        let selected_path = current_project(state).map(|p| p.path.clone());
+        rebuild_filter(state);
+        if let Some(path) = selected_path {
+            select_path(state, &path);
+        }
+    }
+}
+
+fn poll_size(state: &mut AppState) {
+    let Some(rx) = &state.size_rx else {
+        return;
+    };
+    match rx.try_recv() {
+        Ok((path, size)) => {
+            if let Some(entry) = state.size_cache.get_mut(&path) {
+                entry.size = Some(size);
+            }
+            state.size_rx = None;
+        }
+        Err(TryRecvError::Empty) => {}
+        Err(TryRecvError::Disconnected) => state.size_rx = None,
+    }
+}
+
+fn find_readme(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .find(|e| e.file_name().to_string_lossy().eq_ignore_ascii_case("readme.md"))
+        .map(|e| e.path())
+}
+
+// Drop the Markdown syntax that reads badly in a terminal: headers, bullets, fences
+fn strip_markdown(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("```") {
+        return None;
+    }
+    let heading = trimmed.trim_start_matches('#');
+    if heading.len() != trimmed.len() {
+        return Some(heading.trim().to_string());
+    }
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(rest) = trimmed.strip_prefix(bullet) {
+            let indent = &line[..line.len() - trimmed.len()];
+            return Some(format!("{indent}• {rest}"));
+        }
+    }
+    Some(line.to_string())
+}
+
+fn load_readme(dir: &Path) -> ReadmePreview {
+    let file = find_readme(dir);
+    let lines = file.as_ref().and_then(|f| fs::read_to_string(f).ok()).map(|text| {
+        text.lines()
+            .filter_map(strip_markdown)
+            .take(README_LINES)
+            .collect()
+    });
+    ReadmePreview {
+        dir_mtime: mtime_of(dir),
+        file_mtime: file.as_deref().and_then(mtime_of),
+        file,
+        lines,
+    }
+}
+
+// Make sure the selected project's preview is loaded and current. A new README bumps
+// the directory mtime; an edited one bumps its own.
+fn refresh_readme(state: &mut AppState) {
+    let Some(dir) = details_project(state).map(|p| p.path.clone()) else {
+        return;
+    };
+    let fresh = state.readme_cache.get(&dir).is_some_and(|r| {
+        r.dir_mtime == mtime_of(&dir) && r.file_mtime == r.file.as_deref().and_then(mtime_of)
+    });
+    if !fresh {
+        let preview = load_readme(&dir);
+        state.readme_cache.insert(dir, preview);
+    }
+}
+
+fn scroll_readme(state: &mut AppState, down: bool) {
+    let Some(p) = details_project(state) else {
+        return;
+    };
+    let max = state
+        .readme_cache
+        .get(&p.path)
+        .and_then(|r| r.lines.as_ref())
+        .map_or(0, |l| l.len().saturating_sub(1));
+    let current = if state.readme_scroll.0 == p.path {
+        state.readme_scroll.1
+    } else {
+        0
+    };
+    let offset = if down {
+        (current + 1).min(max)
+    } else {
+        current.saturating_sub(1)
+    };
+    state.readme_scroll = (p.path.clone(), offset);
+}
+
+// Last path component, or the whole path when there is none (`/`, `..`)
+fn dir_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string_lossy().to_string())
+}
+
+// Config name when the project has one, directory name otherwise
+fn display_name(p: &Project) -> String {
+    p.cfg
+        .as_ref()
+        .map(|c| c.name.clone())
+        .filter(|n| !n.is_empty())
+        .unwrap_or_else(|| dir_name(&p.path))
+}
+
+// Directory names can repeat across roots, so prefix the root's name when they do
+fn list_label(state: &AppState, p: &Project) -> String {
+    let label = display_name(p);
+    let duplicated = state
+        .projects
+        .iter()
+        .any(|o| o.path != p.path && o.path.file_name() == p.path.file_name());
+    let label = match p.root.file_name() {
+        Some(parent) if duplicated => format!("{}/{}", parent.to_string_lossy(), label),
+        _ => label,
+    };
+    let label = if state.pinned.contains(&p.path) {
+        format!("★ {label}")
+    } else {
+        label
+    };
+    if p.archived {
+        format!("{label} [archived]")
+    } else {
+        label
+    }
+}
+
+fn current_project(state: &AppState) -> Option<&Project> {
+    state
+        .filtered
+        .get(state.selected)
+        .and_then(|&i| state.projects.get(i))
+}
+
+// What the details pane shows: the locked project, else the selection
+fn details_project(state: &AppState) -> Option<&Project> {
+    match state.locked {
+        Some(i) => state.projects.get(i),
+        None => current_project(state),
+    }
+}
+
+fn list_width(state: &AppState, cols: i32) -> i32 {
+    (cols.max(0) as f64 * state.list_ratio) as i32
+}
+
+// Widen (positive) or narrow the list pane, staying within 20%..80%
+fn adjust_list_ratio(state: &mut AppState, delta: f64) {
+    let ratio = (state.list_ratio + delta).clamp(0.20, 0.80);
+    // Round away float drift so the saved value stays tidy
+    state.list_ratio = (ratio * 100.0).round() / 100.0;
+}
+
+// Save anything the user changed at runtime; called on every way out of the TUI
+fn persist_settings(state: &AppState) -> Result<()> {
+    if (state.list_ratio - state.config.list_ratio).abs() > f64::EPSILON {
+        save_app_setting("list_ratio", state.list_ratio.into())?;
+    }
+    if state.expanded_rows != state.config.expanded_rows {
+        save_app_setting("expanded_rows", state.expanded_rows.into())?;
+    }
+    if state.config.restore_session {
+        save_session(&Session {
+            selected: current_project(state).map(|p| p.path.clone()),
+            filter: state.filter_text.clone(),
+        })?;
+    }
+    Ok(())
+}
+
+// Rows available to the project list, between its title and the status line
+fn list_height() -> usize {
+    let mut rows = 0;
+    let mut cols = 0;
+    getmaxyx(stdscr(), &mut rows, &mut cols);
+    (rows - LIST_TOP - 2).max(0) as usize
+}
+
+// Adjust scroll_top so the selected row stays on screen
+fn ensure_visible(state: &mut AppState) {
+    let height = list_height().max(1);
+    let list = list_rows(state);
+    let row = selected_row(&list, state.selected);
+    // Bring the group's header along when scrolling up to its first project
+    let top = match row.checked_sub(1).map(|r| list[r]) {
+        Some(ListRow::Header(_)) => row - 1,
+        _ => row,
+    };
+    if top < state.scroll_top {
+        state.scroll_top = top;
+    } else if row >= state.scroll_top + height {
+        state.scroll_top = row + 1 - height;
+    }
+    let max_top = list.len().saturating_sub(height);
+    state.scroll_top = state.scroll_top.min(max_top);
+}
+
+// Headers of the date grouping (H), in list order
+const DATE_GROUPS: [&str; 4] = ["This week", "Last month", "Older", "Undated"];
+
+// Index into DATE_GROUPS by created_at; anything dated in the future counts as this week
+fn date_group(p: &Project) -> usize {
+    let Some(t) = created_at(p) else {
+        return 3;
+    };
+    let age = Utc::now().signed_duration_since(t);
+    if age < chrono::Duration::days(7) {
+        0
+    } else if age < chrono::Duration::days(31) {
+        1
+    } else {
+        2
+    }
+}
+
+// A line of the project list: a date header while grouping, or a project by its
+// position in `filtered`. `selected` only ever points at projects, so moving
+// through the list skips the headers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ListRow {
+    Header(usize),
+    Project(usize),
+}
+
+fn list_rows(state: &AppState) -> Vec<ListRow> {
+    let mut rows = Vec::with_capacity(state.filtered.len());
+    let mut group = None;
+    for (pos, &i) in state.filtered.iter().enumerate() {
+        if state.group_by_date {
+            let g = date_group(&state.projects[i]);
+            if group != Some(g) {
+                rows.push(ListRow::Header(g));
+                group = Some(g);
+            }
+        }
+        rows.push(ListRow::Project(pos));
+    }
+    rows
+}
+
+fn selected_row(rows: &[ListRow], selected: usize) -> usize {
+    rows.iter().position(|&r| r == ListRow::Project(selected)).unwrap_or(0)
+}
+
+// Turn a mouse event into the key it stands for: the wheel moves like the arrows and
+// a double click opens like Enter. A click on a row selects it; ERR when nothing's left
+// to do.
+fn mouse_key(state: &mut AppState) -> i32 {
+    let mut ev = MEVENT { id: 0, x: 0, y: 0, z: 0, bstate: 0 };
+    if getmouse(&mut ev) != OK {
+        return ERR;
+    }
+    let pressed = |button: i32| ev.bstate & button as mmask_t != 0;
+    if pressed(BUTTON4_PRESSED) {
+        return KEY_UP;
+    }
+    if pressed(BUTTON5_PRESSED) {
+        return KEY_DOWN;
+    }
+    let mut rows = 0;
+    let mut cols = 0;
+    getmaxyx(stdscr(), &mut rows, &mut cols);
+    // Same layout as draw: rows start at LIST_TOP, inside the list pane's border
+    let row = ev.y - LIST_TOP;
+    if ev.x < 1 || ev.x >= list_width(state, cols) - 1 || row < 0 || row >= list_height() as i32 {
+        return ERR;
+    }
+    // Headers can't be selected
+    match list_rows(state).get(state.scroll_top + row as usize) {
+        Some(&ListRow::Project(pos)) => state.selected = pos,
+        _ => return ERR,
+    }
+    if pressed(BUTTON1_DOUBLE_CLICKED) { 10 } else { ERR }
+}
+
+// Replace the filter with tag:<the selection's nth tag>, keeping the selection
+fn filter_by_tag(state: &mut AppState, n: usize) {
+    let Some(p) = details_project(state) else {
+        return;
+    };
+    let Some(tag) = p.cfg.as_ref().and_then(|c| c.tags.get(n)).cloned() else {
+        return;
+    };
+    let path = p.path.clone();
+    state.filter_text = format!("tag:{tag}");
+    rebuild_filter(state);
+    state.selected = 0;
+    select_path(state, &path);
+}
+
+// PageUp / PageDown: move the selection and the view by one screenful, stopping
+// exactly on the first / last project
+fn page_move(state: &mut AppState, down: bool) {
+    let height = list_height().max(1);
+    let last = state.filtered.len().saturating_sub(1);
+    if down {
+        state.selected = (state.selected + height).min(last);
+        state.scroll_top += height;
+    } else {
+        state.selected = state.selected.saturating_sub(height);
+        state.scroll_top = state.scroll_top.saturating_sub(height);
+    }
+    // Clamped back to a valid range by ensure_visible
+    let row = selected_row(&list_rows(state), state.selected);
+    state.scroll_top = state.scroll_top.min(row);
+}
+
+// Index into `state.projects` for the selected row
+fn current_index(state: &AppState) -> Option<usize> {
+    state.filtered.get(state.selected).copied()
+}
+
+fn parse_tags(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_string())
+        .collect()
+}
+
+fn edit_tags(p: &mut Project) -> Result<bool> {
+    let current = p.cfg.as_ref().map(|c| c.tags.join(", ")).unwrap_or_default();
+    let Some(input) = prompt_input("Tags (comma separated): ", &current) else {
+        return Ok(false);
+    };
+    set_tags(p, parse_tags(&input))?;
+    Ok(true)
+}
+
+// Add or remove tags on every project the filter lists. Only configs whose tags
+// change are written; adding gives unconfigured projects a default config first.
+fn retag_filtered(state: &mut AppState, tags: &[String], add: bool) {
+    let selected_path = current_project(state).map(|p| p.path.clone());
+    let mut changed = 0;
+    let mut failed = 0;
+    let mut first_err = None;
+    for i in state.filtered.clone() {
+        let p = &mut state.projects[i];
+        match retag(p, tags, add) {
+            Ok(true) => changed += 1,
+            Ok(false) => {}
+            Err(e) => {
+                failed += 1;
+                first_err.get_or_insert_with(|| format!("{}: {e:#}", display_name(p)));
+            }
+        }
+    }
+    rebuild_filter(state);
+    if let Some(path) = selected_path {
+        select_path(state, &path);
+    }
+    let summary = batch_summary(changed, failed, if add { "tagged" } else { "untagged" });
+    match first_err {
+        Some(e) => flash_error(&format!("{summary} ({e})")),
+        None => flash_ok(&summary),
+    }
+}
+
+// Whether the project's tags changed
+fn retag(p: &mut Project, tags: &[String], add: bool) -> Result<bool> {
+    let created = add && p.cfg.is_none();
+    if created {
+        add_default_config(p)?;
+    }
+    let current = p.cfg.as_ref().map(|c| c.tags.clone()).unwrap_or_default();
+    let new: Vec<String> = if add {
+        let missing = tags.iter().filter(|t| !current.contains(t));
+        current.iter().chain(missing).cloned().collect()
+    } else {
+        current.iter().filter(|t| !tags.contains(t)).cloned().collect()
+    };
+    if new == current {
+        return Ok(created);
+    }
+    set_tags(p, new)?;
+    Ok(true)
+}
+
+fn set_tags(p: &mut Project, tags: Vec<String>) -> Result<()> {
+    if p.cfg.is_none() {
+        add_default_config(p)?;
+    }
+    let Some(cfg) = p.cfg.as_mut() else {
+        return Ok(());
+    };
+    cfg.tags = tags;
+    write_config(&p.path, cfg)
+}
+
+fn edit_name(p: &mut Project) -> Result<bool> {
+    if p.cfg.is_none() {
+        add_default_config(p)?;
+    }
+    let Some(cfg) = p.cfg.as_mut() else {
+        return Ok(false);
+    };
+    let Some(input) = prompt_input("Name: ", &cfg.name) else {
+        return Ok(false);
+    };
+    if input.trim().is_empty() {
+        return Err(anyhow::anyhow!("Name cannot be empty"));
+    }
+    cfg.name = input.trim().to_string();
+    write_config(&p.path, cfg)?;
+    Ok(true)
+}
+
+// Short descriptions are edited inline; long or multi-line ones go through $EDITOR
+fn edit_description(p: &mut Project) -> Result<bool> {
+    if p.cfg.is_none() {
+        add_default_config(p)?;
+    }
+    let Some(cfg) = p.cfg.as_mut() else {
+        return Ok(false);
+    };
+    let mut rows = 0;
+    let mut cols = 0;
+    getmaxyx(stdscr(), &mut rows, &mut cols);
+    let label = "Description: ";
+    let fits_inline = cfg.description.len() + label.len() + 4 < cols as usize
+        && !cfg.description.contains('\n');
+
+    let edited = if fits_inline {
+        prompt_input(label, &cfg.description)
+    } else {
+        edit_in_editor(&cfg.description)?
+    };
+    let Some(edited) = edited else {
+        return Ok(false);
+    };
+    cfg.description = edited.trim().to_string();
+    write_config(&p.path, cfg)?;
+    Ok(true)
+}
+
+// Ask for name, description and tags in turn and write them in one go.
+// Esc at any prompt cancels without writing anything (Ok(false)).
+fn config_wizard(p: &mut Project) -> Result<bool> {
+    if let Some(err) = &p.cfg_error {
+        return Err(anyhow::anyhow!("{err}"));
+    }
+    let Some(name) = prompt_input("Name: ", &dir_name(&p.path)) else {
+        return Ok(false);
+    };
+    let Some(description) = prompt_input("Description: ", "") else {
+        return Ok(false);
+    };
+    let Some(tags) = prompt_input("Tags (comma separated): ", &detect_tags(&p.path).join(", "))
+    else {
+        return Ok(false);
+    };
+    let name = match name.trim() {
+        "" => dir_name(&p.path),
+        n => n.to_string(),
+    };
+    let cfg = ProjectConfig {
+        name,
+        description: description.trim().to_string(),
+        tags: parse_tags(&tags),
+        created_at: Utc::now().to_rfc3339(),
+        language: None,
+        repo_url: None,
+        commands: Vec::new(),
+    };
+    write_config(&p.path, &cfg)?;
+    p.cfg = Some(cfg);
+    Ok(true)
+}
+
+fn add_default_config(p: &mut Project) -> Result<()> {
+    // Don't clobber a config the user only needs to fix
+    if let Some(err) = &p.cfg_error {
+        return Err(anyhow::anyhow!("{err}"));
+    }
+    write_default_config(&p.path)?;
+    p.cfg = read_config(&p.path)?;
+    Ok(())
+}
+
+// Score `needle` as a subsequence of `hay`, or None if it isn't one.
+// Plain substring matches always outrank scattered ones.
+fn fuzzy_score(hay: &str, needle: &str) -> Option<i64> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    if let Some(pos) = hay.find(needle) {
+        return Some(10_000 - pos as i64);
+    }
+
+    let hay: Vec<char> = hay.chars().collect();
+    let mut score = 0;
+    let mut start = 0;
+    let mut prev: Option<usize> = None;
+    for nc in needle.chars() {
+        let pos = start + hay[start..].iter().position(|&c| c == nc)?;
+        score += 10;
+        match prev {
+            // Consecutive characters
+            Some(p) if p + 1 == pos => score += 15,
+            // Penalize the gap since the previous hit
+            Some(p) => score -= (pos - p - 1) as i64,
+            None => score -= pos as i64,
+        }
+        // Start of a word
+        if pos == 0 || !hay[pos - 1].is_alphanumeric() {
+            score += 10;
+        }
+        prev = Some(pos);
+        start = pos + 1;
+    }
+    Some(score)
+}
+
+// One whitespace-separated piece of the filter; all pieces must match
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FilterToken {
+    // tag:<v> matches against the tags only
+    Tag(String),
+    // name:<v> matches against the name only
+    Name(String),
+    // is:<v> matches on the git state, see IS_VALUES
+    Is(String),
+    // desc:<v> looks for the text in the description; plain tokens never do
+    Desc(String),
+    // Anything else matches name and tags
+    Any(String),
+}
+
+fn parse_filter(f: &str) -> Vec<FilterToken> {
+    f.split_whitespace()
+        .map(|tok| {
+            if let Some(v) = tok.strip_prefix("tag:") {
+                FilterToken::Tag(v.to_string())
+            } else if let Some(v) = tok.strip_prefix("name:") {
+                FilterToken::Name(v.to_string())
+            } else if let Some(v) = tok.strip_prefix("is:") {
+                FilterToken::Is(v.to_string())
+            } else if let Some(v) = tok.strip_prefix("desc:") {
+                FilterToken::Desc(v.to_string())
+            } else {
+                FilterToken::Any(tok.to_string())
+            }
+        })
+        .collect()
+}
+
+// Sum of the token scores, or None if any token fails. Empty values match everything.
+fn filter_score(
+    tokens: &[FilterToken],
+    name: &str,
+    tags: &[String],
+    language: &str,
+    description: &str,
+) -> Option<i64> {
+    let mut total = 0;
+    for tok in tokens {
+        total += match tok {
+            FilterToken::Tag(v) => {
+                if !v.is_empty() && !tags.iter().any(|t| t.contains(v.as_str())) {
+                    return None;
+                }
+                0
+            }
+            FilterToken::Name(v) => fuzzy_score(name, v)?,
+            // Checked against the project by project_score
+            FilterToken::Is(_) => 0,
+            // Plain substring; fuzzy matching over prose matches nearly anything
+            FilterToken::Desc(v) => {
+                if !v.is_empty() && !description.contains(v.as_str()) {
+                    return None;
+                }
+                0
+            }
+            FilterToken::Any(v) => {
+                fuzzy_score(&format!("{name} {} {language}", tags.join(",")), v)?
+            }
+        };
+    }
+    Some(total)
+}
+
+// Kept outside fylex.config.json so it never ends up committed with the project
+fn usage_path() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("usage.json"))
+}
+
+fn load_usage() -> HashMap<PathBuf, String> {
+    usage_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save_usage(usage: &HashMap<PathBuf, String>) -> Result<()> {
+    let Some(p) = usage_path() else {
+        return Ok(());
+    };
+    if let Some(dir) = p.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(p, serde_json::to_string_pretty(usage)?)?;
+    Ok(())
+}
+
+// Failing to persist usage shouldn't stop the project from opening
+fn record_open(state: &mut AppState, path: &Path) {
+    state.usage.insert(path.to_path_buf(), Utc::now().to_rfc3339());
+    let _ = save_usage(&state.usage);
+}
+
+// Where the last run left off
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Session {
+    selected: Option<PathBuf>,
+    filter: String,
+}
+
+fn session_path() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("session.json"))
+}
+
+fn load_session() -> Session {
+    session_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save_session(session: &Session) -> Result<()> {
+    let Some(p) = session_path() else {
+        return Ok(());
+    };
+    if let Some(dir) = p.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(p, serde_json::to_string_pretty(session)?)?;
+    Ok(())
+}
+
+// Put back the saved filter and selection; a project that's gone leaves the top selected
+fn restore_session(state: &mut AppState) {
+    let session = load_session();
+    state.filter_text = session.filter;
+    rebuild_filter(state);
+    state.selected = 0;
+    if let Some(path) = session.selected {
+        select_path(state, &path);
+    }
+}
+
+fn favorites_path() -> Option<PathBuf> {
+    data_dir().map(|d| d.join("favorites.json"))
+}
+
+fn load_favorites() -> HashSet<PathBuf> {
+    favorites_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save_favorites(pinned: &HashSet<PathBuf>) -> Result<()> {
+    let Some(p) = favorites_path() else {
+        return Ok(());
+    };
+    if let Some(dir) = p.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Sorted so the file diffs nicely
+    let mut list: Vec<&PathBuf> = pinned.iter().collect();
+    list.sort();
+    fs::write(p, serde_json::to_string_pretty(&list)?)?;
+    Ok(())
+}
+
+fn last_opened(
+    usage: &HashMap<PathBuf, String>,
+    p: &Project,
+) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    usage
+        .get(&p.path)
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+}
+
+// "3 days ago" style
+fn relative_time<Tz: chrono::TimeZone>(then: &chrono::DateTime<Tz>) -> String {
+    let secs = Utc::now().signed_duration_since(then).num_seconds();
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("1 {unit} ago")
+        } else {
+            format!("{n} {unit}s ago")
+        }
+    };
+    match secs {
+        s if s < 60 => "just now".to_string(),
+        s if s < 3600 => plural(s / 60, "minute"),
+        s if s < 86_400 => plural(s / 3600, "hour"),
+        s if s < 30 * 86_400 => plural(s / 86_400, "day"),
+        s if s < 365 * 86_400 => plural(s / (30 * 86_400), "month"),
+        s => plural(s / (365 * 86_400), "year"),
+    }
+}
+
+// "3 days ago", or local date and time once toggled with @
+fn format_time(state: &AppState, t: &chrono::DateTime<chrono::FixedOffset>) -> String {
+    if state.absolute_times {
+        t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string()
+    } else {
+        relative_time(t)
+    }
+}
+
+fn created_at(p: &Project) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    p.cfg
+        .as_ref()
+        .and_then(|c| chrono::DateTime::parse_from_rfc3339(&c.created_at).ok())
+}
+
+// Projects created this recently get a NEW badge (the created sort lists them first)
+const NEW_DAYS: i64 = 7;
+
+// Without a valid created_at a project is never new
+fn is_new(p: &Project) -> bool {
+    created_at(p).is_some_and(|t| {
+        let age = Utc::now().signed_duration_since(t);
+        age >= chrono::Duration::zero() && age < chrono::Duration::days(NEW_DAYS)
+    })
+}
+
+// Where a git state goes in the dirty sort, higher first. The state codes are
+// just labels, their numeric order means nothing.
+fn dirty_rank(git_state: u8) -> u8 {
+    match git_state {
+        2 => 4,
+        3 => 3,
+        4 => 2,
+        1 => 1,
+        _ => 0,
+    }
+}
+
+// Missing values always sort last; ties fall back to the scan order
+fn sort_cmp(
+    mode: SortMode,
+    usage: &HashMap<PathBuf, String>,
+    a: &Project,
+    b: &Project,
+) -> Ordering {
+    fn last_if_none<T: Ord>(a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => b.cmp(&a),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+    match mode {
+        SortMode::Name => Ordering::Equal,
+        SortMode::Created => last_if_none(created_at(a), created_at(b)),
+        SortMode::Dirty => {
+            last_if_none(a.git_state.map(dirty_rank), b.git_state.map(dirty_rank))
+        }
+        SortMode::Modified => last_if_none(a.mtime, b.mtime),
+        SortMode::Opened => last_if_none(last_opened(usage, a), last_opened(usage, b)),
+    }
+}
+
+// No fylex.config.json at all (a broken one doesn't count)
+fn is_unconfigured(p: &Project) -> bool {
+    p.cfg.is_none() && p.cfg_error.is_none()
+}
+
+// Values `is:` understands
+const IS_VALUES: &[&str] = &["dirty", "clean", "nogit", "nocfg"];
+
+// Whether the project is in the `is:<v>` state (git, or nocfg for folders without a
+// fylex.config.json); unknown values match nothing.
+// Repositories whose state is still being computed are neither dirty nor clean.
+fn is_match(v: &str, p: &Project) -> bool {
+    match v {
+        "" => true,
+        "dirty" => matches!(p.git_state, Some(2 | 3)),
+        "clean" => p.git_state == Some(1),
+        "nogit" => p.git_state.is_none() && !p.git_pending,
+        "nocfg" => is_unconfigured(p),
+        _ => false,
+    }
+}
+
+// The text as it's compared: lowercased unless matching is case-sensitive
+fn filter_case(s: &str, case_sensitive: bool) -> String {
+    if case_sensitive {
+        s.to_string()
+    } else {
+        s.to_lowercase()
+    }
+}
+
+// How well `p` matches the parsed filter, or None if it doesn't. The tokens must
+// have gone through filter_case with the same flag.
+fn project_score(tokens: &[FilterToken], p: &Project, case_sensitive: bool) -> Option<i64> {
+    for tok in tokens {
+        if let FilterToken::Is(v) = tok
+            && !is_match(v, p)
+        {
+            return None;
+        }
+    }
+    let name = filter_case(&display_name(p), case_sensitive);
+
+    let tags = p
+        .cfg
+        .as_ref()
+        .map(|c| c.tags.iter().map(|t| filter_case(t, case_sensitive)).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let language = p.cfg.as_ref().and_then(|c| c.language.as_deref()).unwrap_or_default();
+    let language = filter_case(language, case_sensitive);
+
+    let description = p.cfg.as_ref().map(|c| c.description.as_str()).unwrap_or_default();
+    let description = filter_case(description, case_sensitive);
+
+    filter_score(tokens, &name, &tags, &language, &description)
+}
+
+// `re:<pattern>` filters with a regex over name, tags and language instead of
+// tokens; None when the filter isn't a regex
+fn compile_filter_regex(text: &str, case_sensitive: bool) -> Option<Result<regex::Regex, String>> {
+    let pattern = text.strip_prefix("re:")?;
+    Some(
+        regex::RegexBuilder::new(pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            // Syntax errors span several lines around a caret; the last one says what's wrong
+            .map_err(|e| {
+                let msg = e.to_string();
+                let last = msg.lines().last().unwrap_or_default().trim();
+                last.strip_prefix("error: ").unwrap_or(last).to_string()
+            }),
+    )
+}
+
+fn rebuild_filter(state: &mut AppState) {
+    let tokens = parse_filter(&filter_case(&state.filter_text, state.case_sensitive));
+    state.filter_regex = compile_filter_regex(&state.filter_text, state.case_sensitive);
+    state.filtered.clear();
+    let mut scored = Vec::new();
+
+    for (i, p) in state.projects.iter().enumerate() {
+        let score = match &state.filter_regex {
+            // An invalid regex matches nothing until it's fixed
+            Some(Err(_)) => None,
+            Some(Ok(re)) => {
+                let cfg = p.cfg.as_ref();
+                let tags = cfg.map(|c| c.tags.join(",")).unwrap_or_default();
+                let language = cfg.and_then(|c| c.language.as_deref()).unwrap_or_default();
+                let hay = format!("{} {tags} {language}", display_name(p));
+                re.is_match(&hay).then_some(0)
+            }
+            None => project_score(&tokens, p, state.case_sensitive),
+        };
+        if let Some(score) = score {
+            scored.push((i, score));
+        }
+    }
+    // Date group when grouping, then pinned, then match score, then the active
+    // sort; stable so the alphabetical scan order breaks ties
+    let projects = &state.projects;
+    let sort = state.sort;
+    let usage = &state.usage;
+    let group = |i: usize| state.group_by_date.then(|| date_group(&projects[i]));
+    let pinned = |i: usize| state.pinned.contains(&projects[i].path);
+    scored.sort_by(|a, b| {
+        group(a.0)
+            .cmp(&group(b.0))
+            .then_with(|| pinned(b.0).cmp(&pinned(a.0)))
+            .then_with(|| b.1.cmp(&a.1))
+            .then_with(|| sort_cmp(sort, usage, &projects[a.0], &projects[b.0]))
+    });
+    state.filtered.extend(scored.into_iter().map(|(i, _)| i));
+    if state.selected >= state.filtered.len() {
+        state.selected = state.filtered.len().saturating_sub(1);
+    }
+}
+
+// ncurses has already picked up the new size by the time it hands us KEY_RESIZE (its own
+// SIGWINCH handler interrupts getch, so don't install another one over it)
+fn handle_resize(state: &mut AppState) {
+    erase();
+    state.selected = state.selected.min(state.filtered.len().saturating_sub(1));
+    ensure_visible(state);
+}
+
+fn select_path(state: &mut AppState, path: &Path) {
+    if let Some(pos) = state
+        .filtered
+        .iter()
+        .position(|&i| state.projects[i].path == path)
+    {
+        state.selected = pos;
+    }
+}
+
+// Rescan every root, keeping the cursor on the same project when it still exists.
+// On error the current list is left untouched.
+fn reload_projects(state: &mut AppState, force: bool) -> Result<Vec<String>> {
+    let selected_path = current_project(state).map(|p| p.path.clone());
+    let locked_path = state.locked.and_then(|i| state.projects.get(i)).map(|p| p.path.clone());
+    let archive = state.show_archived.then_some(state.archive_root.as_path());
+    let opts = ScanOptions::new(&state.config, state.show_hidden, &state.ignore_globs);
+    let (projects, skipped) = scan_projects(&state.roots, archive, &opts)?;
+    state.projects = projects;
+    let projects = &state.projects;
+    state.marked.retain(|m| projects.iter().any(|p| &p.path == m));
+    // Indices changed; stays locked as long as the project is still there
+    state.locked = locked_path.and_then(|l| projects.iter().position(|p| p.path == l));
+    start_git_scan(state, force);
+    rebuild_filter(state);
+    if let Some(path) = selected_path {
+        select_path(state, &path);
+    }
+    // New projects need their git directories watched too. The new list stands
+    // either way; a root that can't be watched any more just turns watch mode off.
+    if state.watch.is_some() {
+        state.watch = None;
+        if let Err(e) = start_watch(state) {
+            flash_error(&format!("Watch stopped: {e:#}"));
+        }
+    }
+    Ok(skipped)
+}
+
+// Keys while composing the filter: printable keys type, Enter/ESC go back to normal mode
+fn handle_filter_key(state: &mut AppState, ch: i32) {
+    match ch {
+        10 | KEY_ENTER | 27 => state.mode = Mode::Normal,
+        127 | KEY_BACKSPACE => {
+            state.filter_text.pop();
+            rebuild_filter(state);
+            state.selected = 0;
+        }
+        KEY_UP if state.selected > 0 => {
+            state.selected -= 1;
+        }
+        KEY_DOWN if state.selected + 1 < state.filtered.len() => {
+            state.selected += 1;
+        }
+        KEY_PPAGE => page_move(state, false),
+        KEY_NPAGE => page_move(state, true),
+        c if (32..=126).contains(&c) || (0xC2..=0xF4).contains(&c) => {
+            let Some(c) = read_utf8_char(c) else {
+                return;
+            };
+            state.filter_text.push(c);
+            rebuild_filter(state);
+            // Best match is on top
+            state.selected = 0;
+        }
+        _ => {}
+    }
+}
+
+// getch hands over multi-byte input one byte at a time; collect the rest of the
+// sequence that starts with `first` and decode it
+fn read_utf8_char(first: i32) -> Option<char> {
+    let len = match first {
+        0x00..=0x7F => 1,
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return None,
+    };
+    let mut bytes = vec![first as u8];
+    while bytes.len() < len {
+        let b = getch();
+        if !(0x80..=0xBF).contains(&b) {
+            return None;
+        }
+        bytes.push(b as u8);
+    }
+    std::str::from_utf8(&bytes).ok()?.chars().next()
+}
+
+fn open_in_terminal(path: &Path) -> Result<()> {
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/bash".to_string());
+    let mut cmd = Command::new(&shell);
+    cmd.current_dir(path);
+    hand_over_terminal(cmd)
+}
+
+// Replace fylex with `cmd`; only returns on failure
+fn hand_over_terminal(mut cmd: Command) -> Result<()> {
+    // Restore terminal before handing control to the user's shell
+    endwin();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Clear screen before handing off to the shell (exec never returns on success)
+        print!("\x1B[2J\x1B[H");
+        let _ = std::io::stdout().flush();
+        let err = cmd.exec();
+        // If exec returns, it failed
+        Err(anyhow::anyhow!(format!("exec failed: {err}")))
+    }
+
+    #[cfg(not(unix))]
+    {
+        // Fallback: run and wait, then exit with the same code
+        let status = cmd.status()?;
+        process::exit(status.code().unwrap_or(0));
+    }
+}
+
+fn tmux_available() -> bool {
+    Command::new("tmux")
+        .arg("-V")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+// tmux rejects '.' and ':' in session names
+fn tmux_session_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c == '.' || c == ':' || c.is_whitespace() { '_' } else { c })
+        .collect()
+}
+
+// Inside tmux, create the session in the background and switch to it while fylex keeps
+// running. Outside tmux, fylex is replaced by `tmux new-session -A`.
+fn open_in_tmux(path: &Path, name: &str) -> Result<()> {
+    let session = tmux_session_name(name);
+    if std::env::var_os("TMUX").is_none() {
+        let mut cmd = Command::new("tmux");
+        cmd.args(["new-session", "-A", "-s", &session, "-c"]).arg(path);
+        return hand_over_terminal(cmd);
+    }
+
+    let target = format!("={session}");
+    let exists = Command::new("tmux")
+        .args(["has-session", "-t", &target])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success());
+    if !exists {
+        let out = Command::new("tmux")
+            .args(["new-session", "-d", "-s", &session, "-c"])
+            .arg(path)
+            .output()?;
+        if !out.status.success() {
+            return Err(anyhow::anyhow!(
+                "{}",
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+    }
+    let out = Command::new("tmux")
+        .args(["switch-client", "-t", &target])
+        .output()?;
+    if !out.status.success() {
+        return Err(anyhow::anyhow!("{}", String::from_utf8_lossy(&out.stderr).trim()));
+    }
+    Ok(())
+}
+
+// Launch the file manager detached so fylex keeps running
+fn open_in_file_manager(path: &Path) -> Result<()> {
+    let cmd = std::env::var("FYLEX_FILE_MANAGER")
+        .ok()
+        .filter(|c| !c.trim().is_empty())
+        .unwrap_or_else(|| "xdg-open".to_string());
+
+    spawn_detached(Command::new(&cmd).arg(path))
+        .with_context(|| format!("`{cmd} {}` failed", path.display()))
+}
+
+// repo_url if the config has one, else origin's URL, as a page a browser can show
+fn repo_web_url(p: &Project) -> Option<String> {
+    let url = p
+        .cfg
+        .as_ref()
+        .and_then(|c| c.repo_url.clone())
+        .or_else(|| git_output(&p.path, &["remote", "get-url", "origin"]))?;
+    web_url(url.trim())
+}
+
+// git@github.com:u/r.git and ssh://git@github.com/u/r.git become
+// https://github.com/u/r; local paths have no web page
+fn web_url(url: &str) -> Option<String> {
+    let rest = if let Some(rest) = url.strip_prefix("https://").or(url.strip_prefix("http://")) {
+        rest.to_string()
+    } else if let Some(rest) = url.strip_prefix("ssh://").or(url.strip_prefix("git://")) {
+        // Drop a port, it belongs to ssh and not to the web server
+        match rest.split_once('/') {
+            Some((host, path)) => format!("{}/{path}", host.split(':').next().unwrap_or(host)),
+            None => rest.to_string(),
+        }
+    } else {
+        // scp-like syntax: [user@]host:path
+        let (host, path) = url.split_once(':')?;
+        if host.contains('/') || path.starts_with("//") {
+            return None;
+        }
+        format!("{host}/{path}")
+    };
+    // No user@ (or user:token@) in front of the host
+    let rest = match rest.split_once('/') {
+        Some((host, path)) => {
+            let host = host.rsplit_once('@').map_or(host, |(_, h)| h);
+            format!("{host}/{path}")
+        }
+        None => return None,
+    };
+    let rest = rest.trim_end_matches('/');
+    Some(format!("https://{}", rest.strip_suffix(".git").unwrap_or(rest)))
+}
+
+fn open_in_browser(url: &str) -> Result<()> {
+    spawn_detached(Command::new("xdg-open").arg(url))
+        .with_context(|| format!("`xdg-open {url}` failed"))
+}
+
+// Start a process that outlives nothing of ours: no stdio, its own process group
+fn spawn_detached(command: &mut Command) -> std::io::Result<()> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Own process group so terminal signals aimed at fylex don't reach it
+        command.process_group(0);
+    }
+    let mut child = command.spawn()?;
+    // Reap in the background to avoid leaving a zombie
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+// $VISUAL, then $EDITOR, then vim. The value may carry arguments (e.g. "code -w").
+fn editor_command() -> Command {
+    let editor = std::env::var("VISUAL")
+        .ok()
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vim".to_string());
+    let mut parts = editor.split_whitespace();
+    let mut cmd = Command::new(parts.next().unwrap_or("vim"));
+    cmd.args(parts);
+    cmd
+}
+
+// GUI editors are spawned detached; terminal editors borrow the screen until they exit
+fn open_in_editor(path: &Path, gui_editors: &[String]) -> Result<()> {
+    let mut cmd = editor_command();
+    cmd.arg(path).current_dir(path);
+    let program = Path::new(cmd.get_program())
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let label = format!("`{program} {}`", path.display());
+    if gui_editors.contains(&program) {
+        spawn_detached(&mut cmd).with_context(|| format!("{label} failed"))
+    } else {
+        let status = run_suspended(&mut cmd).with_context(|| format!("{label} failed"))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("{label} exited with {status}"));
+        }
+        Ok(())
+    }
+}
+
+// Build command per language tag, used unless the config overrides it
+const DEFAULT_BUILD_COMMANDS: &[(&str, &str)] = &[
+    ("rust", "cargo build"),
+    ("node", "npm run build"),
+    ("go", "go build ./..."),
+];
+
+// First tag (detected from files, then the configured ones) that has a build command
+fn build_command(config: &AppConfig, p: &Project) -> Option<String> {
+    let configured = p.cfg.as_ref().map(|c| c.tags.clone()).unwrap_or_default();
+    detect_tags(&p.path).into_iter().chain(configured).find_map(|tag| {
+        config.build_commands.get(&tag).cloned().or_else(|| {
+            DEFAULT_BUILD_COMMANDS
+                .iter()
+                .find(|(t, _)| *t == tag)
+                .map(|(_, c)| c.to_string())
+        })
+    })
+}
+
+// Run a shell command in the project with its output on the terminal, and wait for
+// Enter before going back to the TUI so the output can be read
+fn run_shell_command(path: &Path, command: &str) -> Result<std::process::ExitStatus> {
+    endwin();
+    println!("$ {command}");
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command).current_dir(path);
+    // Ctrl+C should stop the build, not fylex: ignore it here and restore the
+    // default in the child (ignored signals survive exec)
+    #[cfg(unix)]
+    let previous = unsafe {
+        use std::os::unix::process::CommandExt;
+        cmd.pre_exec(|| {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            Ok(())
+        });
+        libc::signal(libc::SIGINT, libc::SIG_IGN)
+    };
+    let status = cmd.status();
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, previous);
+    }
+    match &status {
+        Ok(s) => println!("\n[{s}] Press Enter to return to fylex"),
+        Err(e) => println!("\nCould not run {command}: {e}. Press Enter to return to fylex"),
+    }
+    let _ = std::io::stdin().read_line(&mut String::new());
+    refresh();
+    Ok(status?)
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|d| d.join(program).is_file()))
+}
+
+// FYLEX_CLIPBOARD, then the config, then whatever fits the session (Wayland or X11)
+fn clipboard_command(config: &AppConfig) -> Option<String> {
+    if let Ok(cmd) = std::env::var("FYLEX_CLIPBOARD")
+        && !cmd.trim().is_empty()
+    {
+        return Some(cmd);
+    }
+    if let Some(cmd) = &config.clipboard {
+        return Some(cmd.clone());
+    }
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let x11 = std::env::var_os("DISPLAY").is_some();
+    let candidates: &[(&str, &str)] = &[
+        ("wl-copy", "wl-copy"),
+        ("xclip", "xclip -selection clipboard"),
+        ("xsel", "xsel --clipboard --input"),
+        ("pbcopy", "pbcopy"),
+    ];
+    candidates
+        .iter()
+        .filter(|(program, _)| match *program {
+            "wl-copy" => wayland,
+            "xclip" | "xsel" => x11,
+            _ => true,
+        })
+        .find(|(program, _)| on_path(program))
+        .map(|(_, cmd)| cmd.to_string())
+}
+
+fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+// Copy through a clipboard tool, or ask the terminal to do it with OSC 52 when
+// there's none (works over ssh, if the terminal allows it)
+fn copy_to_clipboard(config: &AppConfig, text: &str) -> Result<()> {
+    let Some(command) = clipboard_command(config) else {
+        print!("\x1B]52;c;{}\x07", base64(text.as_bytes()));
+        std::io::stdout().flush()?;
+        return Ok(());
+    };
+    // The tools daemonize to serve the selection, so their output can't be waited on
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Running {command}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("`{command}` failed: {status}"));
+    }
+    Ok(())
+}
+
+// Leave curses mode, run the command attached to the terminal, then restore the screen
+fn run_suspended(cmd: &mut Command) -> Result<std::process::ExitStatus> {
+    endwin();
+    let status = cmd.status();
+    refresh();
+    Ok(status?)
+}
+
+// $PAGER, or less -R so git's colors come through
+fn pager_command() -> Command {
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less -R".to_string());
+    let mut parts = pager.split_whitespace();
+    let mut cmd = Command::new(parts.next().unwrap_or("less"));
+    cmd.args(parts);
+    cmd
+}
+
+// Page through `git diff` (or `git diff --staged`); Ok(false) when there's nothing to show
+fn show_diff(path: &Path, staged: bool) -> Result<bool> {
+    let mut args = vec!["diff"];
+    if staged {
+        args.push("--staged");
+    }
+    let quiet = git_command()
+        .arg("-C")
+        .arg(path)
+        .args(&args)
+        .arg("--quiet")
+        .status()
+        .context("Failed to run git diff")?;
+    if quiet.success() {
+        return Ok(false);
+    }
+    endwin();
+    let res = (|| {
+        let mut git = git_command()
+            .arg("-C")
+            .arg(path)
+            .args(&args)
+            .arg("--color=always")
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to run git diff")?;
+        let diff = git.stdout.take().context("No output from git diff")?;
+        let status = pager_command().stdin(diff).status().context("Failed to run the pager");
+        // Quitting the pager early leaves git blocked on a full pipe
+        let _ = git.kill();
+        let _ = git.wait();
+        status
+    })();
+    refresh();
+    let status = res?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Pager exited with {status}"));
+    }
+    Ok(true)
+}
+
+// Hand-edit fylex.config.json (written with defaults first if there's none), then
+// read it back. A file that no longer parses leaves the project marked malformed.
+fn edit_config_file(p: &mut Project) -> Result<()> {
+    let path = p.path.join(CONFIG_NAME);
+    if !path.exists() {
+        write_default_config(&p.path)?;
+    }
+    let status = run_suspended(editor_command().arg(&path).current_dir(&p.path))?;
+    // Re-read even after a failed editor run, the file may have been saved anyway
+    match read_config(&p.path) {
+        Ok(cfg) => {
+            p.cfg = cfg;
+            p.cfg_error = None;
+        }
+        Err(e) => {
+            p.cfg = None;
+            p.cfg_error = Some(format!("{e:#}"));
+        }
+    }
+    if !status.success() {
+        return Err(anyhow::anyhow!("Editor exited with {status}"));
+    }
+    Ok(())
+}
+
+// Open the project's notes in $EDITOR, creating the file first. In a git repository
+// the file is also listed in .git/info/exclude so it never shows up as untracked.
+fn edit_notes(p: &Project) -> Result<()> {
+    let path = p.path.join(NOTES_NAME);
+    if !path.exists() {
+        fs::write(&path, "")?;
+    }
+    if let Some(dir) = git_dir(&p.path) {
+        exclude_from_git(&dir, NOTES_NAME)?;
+    }
+    let status = run_suspended(editor_command().arg(&path).current_dir(&p.path))?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("Editor exited with {status}"));
+    }
+    Ok(())
+}
+
+// Add `name` to the repository's info/exclude unless a line already lists it
+fn exclude_from_git(git_dir: &Path, name: &str) -> Result<()> {
+    let file = git_dir.join("info").join("exclude");
+    let text = fs::read_to_string(&file).unwrap_or_default();
+    let pattern = format!("/{name}");
+    if text.lines().any(|l| l.trim() == name || l.trim() == pattern) {
+        return Ok(());
+    }
+    fs::create_dir_all(git_dir.join("info"))?;
+    let sep = if text.is_empty() || text.ends_with('\n') { "" } else { "\n" };
+    fs::write(&file, format!("{text}{sep}{pattern}\n"))?;
+    Ok(())
+}
+
+// Returns None if the editor exited with an error, leaving the text unchanged
+fn edit_in_editor(initial: &str) -> Result<Option<String>> {
+    let tmp = std::env::temp_dir().join(format!("fylex-{}.txt", std::process::id()));
+    fs::write(&tmp, initial)?;
+    let status = run_suspended(editor_command().arg(&tmp));
+    let text = fs::read_to_string(&tmp);
+    let _ = fs::remove_file(&tmp);
+    if !status?.success() {
+        return Ok(None);
+    }
+    Ok(Some(text?))
+}
+
+fn flash_line(msg: &str, color_pair: i16) {
+    let mut rows = 0;
+    let mut cols = 0;
+    getmaxyx(stdscr(), &mut rows, &mut cols);
+
+    // Keep a copy of the footer to put back once the message times out
+    let saved = newwin(1, cols, 0, 0);
+    copywin(stdscr(), saved, rows - 1, 0, 0, 0, 0, cols - 1, 0);
 
     attron(COLOR_PAIR(color_pair));
     mvhline(rows - 1, 0, ' ' as u32, cols);
@@ -347,6 +3956,10 @@ fn flash_line(msg: &str, color_pair: i16) {
     attroff(COLOR_PAIR(color_pair));
     refresh();
     napms(1500);
+
+    copywin(saved, stdscr(), 0, 0, rows - 1, 0, rows - 1, cols - 1, 0);
+    delwin(saved);
+    refresh();
 }
 
 fn flash_error(msg: &str) {
@@ -357,7 +3970,17 @@ fn flash_ok(msg: &str) {
     flash_line(msg, 4);
 }
 
-fn prompt_input(label: &str, initial: &str) -> String {
+// Returns None when the prompt is cancelled with ESC
+fn prompt_input(label: &str, initial: &str) -> Option<String> {
+    prompt_input_with(label, initial, |_| None)
+}
+
+// prompt_input where Tab replaces the text with what `complete` makes of it
+fn prompt_input_with(
+    label: &str,
+    initial: &str,
+    complete: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
     let mut rows = 0;
     let mut cols = 0;
     getmaxyx(stdscr(), &mut rows, &mut cols);
@@ -368,141 +3991,1993 @@ fn prompt_input(label: &str, initial: &str) -> String {
         mvhline(rows - 1, 0, ' ' as u32, cols);
         let _ = mvprintw(rows - 1, 1, label);
         attroff(COLOR_PAIR(3));
-        let _ = mvprintw(rows - 1, (label.len() + 1) as i32, &buf);
-        mv(rows - 1, (label.len() + 2 + buf.len()) as i32);
+        let label_width = label.chars().count();
+        let _ = mvprintw(rows - 1, (label_width + 1) as i32, &buf);
+        mv(rows - 1, (label_width + 2 + buf.chars().count()) as i32);
         refresh();
-        let ch = getch();
-        match ch {
-            10 => break,
-            27 => { buf.clear(); break; }, // ESC to cancel
-            127 | KEY_BACKSPACE => { buf.pop(); },
-            c if (32..=126).contains(&c) => buf.push(c as u8 as char),
+        // get_wch hands back whole characters, so multi-byte input arrives intact
+        match get_wch() {
+            Some(WchResult::Char(10)) => break,
+            Some(WchResult::Char(27)) => return None, // ESC to cancel
+            Some(WchResult::Char(127)) | Some(WchResult::KeyCode(KEY_BACKSPACE)) => {
+                buf.pop();
+            }
+            Some(WchResult::Char(9)) => {
+                if let Some(done) = complete(&buf) {
+                    buf = done;
+                }
+            }
+            Some(WchResult::Char(c)) => {
+                if let Some(c) = char::from_u32(c)
+                    && !c.is_control()
+                {
+                    buf.push(c);
+                }
+            }
             _ => {}
+        }
+    }
+    Some(buf)
+
+}
+
+// What a line typed at the : prompt asks for
+enum PaletteCommand {
+    // Handled exactly as if the key had been pressed
+    Key(i32),
+    Tags(Vec<String>),
+    // :tag+ / :tag-, on every listed project
+    Retag { add: bool, tags: Vec<String> },
+    Sort(SortMode),
+}
+
+// : commands that stand for a key
+const PALETTE_KEYS: &[(&str, i32)] = &[
+    ("open", 10),
+    ("edit", 99),
+    ("folder", 79),
+    ("browse", 117),
+    ("new", 78),
+    ("clone", 76),
+    ("duplicate", 43),
+    ("rename", 77),
+    ("delete", 88),
+    ("archive", 90),
+    ("hidden", 46),
+    ("config", 70),
+    ("notes", 110),
+    ("pin", 42),
+    ("lock", 108),
+    ("group", 72),
+    ("expand", 105),
+    ("copy", 121),
+    ("tarball", 66),
+    ("build", 98),
+    ("run", 33),
+    ("pull", 112),
+    ("commit", 67),
+    ("diff", 118),
+    ("stash", 83),
+    ("pop", 80),
+    ("watch", 119),
+    ("reload", 114),
+    ("help", 63),
+    ("quit", 113),
+];
+
+// Commands with an argument, on top of PALETTE_KEYS
+const PALETTE_ARGS: &[&str] = &["tag", "tag+", "tag-", "sort"];
+
+fn parse_palette(line: &str) -> Result<PaletteCommand, String> {
+    let line = line.trim();
+    let (name, arg) = line
+        .split_once(char::is_whitespace)
+        .map_or((line, ""), |(n, a)| (n, a.trim()));
+    match name {
+        // Without tags it's the same as T
+        "tag" if arg.is_empty() => Ok(PaletteCommand::Key(84)),
+        "tag" => Ok(PaletteCommand::Tags(parse_tags(arg))),
+        "tag+" | "tag-" if arg.is_empty() => Err(format!(":{name} needs a tag")),
+        "tag+" | "tag-" => Ok(PaletteCommand::Retag {
+            add: name == "tag+",
+            tags: parse_tags(arg),
+        }),
+        "sort" => SortMode::from_label(arg).map(PaletteCommand::Sort).ok_or_else(|| {
+            "Sort by name, created (or date), dirty, modified or opened".to_string()
+        }),
+        _ => match PALETTE_KEYS.iter().find(|(n, _)| *n == name) {
+            Some(_) if !arg.is_empty() => Err(format!(":{name} takes no argument")),
+            Some(&(_, key)) => Ok(PaletteCommand::Key(key)),
+            None => Err(format!("Unknown command :{name}")),
+        },
+    }
+}
+
+// Tab completion for command names: the unique match, else the longest shared prefix
+fn complete_palette(buf: &str) -> Option<String> {
+    if buf.contains(char::is_whitespace) {
+        return None;
+    }
+    let names: Vec<&str> = PALETTE_KEYS
+        .iter()
+        .map(|(n, _)| *n)
+        .chain(PALETTE_ARGS.iter().copied())
+        .filter(|n| n.starts_with(buf))
+        .collect();
+    match names.as_slice() {
+        [] => None,
+        [one] if PALETTE_ARGS.contains(one) => Some(format!("{one} ")),
+        [one] => Some(one.to_string()),
+        [first, rest @ ..] => {
+            let shared = rest.iter().fold(first.len(), |len, n| {
+                first.bytes().zip(n.bytes()).take(len).take_while(|(a, b)| a == b).count()
+            });
+            Some(first[..shared].to_string())
+        }
+    }
+}
+
+// Actions the keymap can bind that aren't : commands, mostly because the palette
+// has an argument version of them
+const KEYMAP_ONLY: &[(&str, i32)] = &[
+    ("filter", 47),
+    ("palette", 58),
+    ("sort", 115),
+    ("tags", 84),
+    ("mark", 32),
+    ("up", 107),
+    ("down", 106),
+    ("top", 103),
+    ("bottom", 71),
+];
+
+fn keymap_actions() -> impl Iterator<Item = &'static (&'static str, i32)> {
+    PALETTE_KEYS.iter().chain(KEYMAP_ONLY)
+}
+
+// A single character, Space, Enter, Tab or Ctrl+<letter>
+fn parse_key_name(name: &str) -> Option<i32> {
+    match name {
+        "Space" => return Some(32),
+        "Enter" => return Some(10),
+        "Tab" => return Some(9),
+        _ => {}
+    }
+    if let Some(c) = name.strip_prefix("Ctrl+") {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => {
+                Some(c.to_ascii_lowercase() as i32 - 96)
+            }
+            _ => None,
+        };
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_graphic() => Some(c as i32),
+        _ => None,
+    }
+}
+
+fn key_label(key: i32) -> String {
+    match key {
+        32 => "Space".to_string(),
+        10 => "Enter".to_string(),
+        9 => "Tab".to_string(),
+        1..=26 => format!("Ctrl+{}", (key + 64) as u8 as char),
+        _ => (key as u8 as char).to_string(),
+    }
+}
+
+// Turn the config's keymap into typed key -> default key. Bad entries are skipped
+// and, like two actions on one key or a key taken from another action, warned about.
+fn build_keymap(config: &HashMap<String, String>) -> (HashMap<i32, i32>, Vec<String>) {
+    let mut keymap = HashMap::new();
+    let mut warnings = Vec::new();
+    let mut entries: Vec<_> = config.iter().collect();
+    entries.sort();
+    for (action, name) in &entries {
+        let Some(&(_, default)) = keymap_actions().find(|(n, _)| n == action) else {
+            warnings.push(format!("keymap: unknown action {action}"));
+            continue;
+        };
+        let Some(key) = parse_key_name(name) else {
+            warnings.push(format!("keymap: invalid key {name} for {action}"));
+            continue;
+        };
+        if let Some(other) = keymap.get(&key).and_then(|&d| action_name(d)) {
+            warnings.push(format!("keymap: {name} is bound to both {other} and {action}"));
+            continue;
+        }
+        keymap.insert(key, default);
+    }
+    // A key that was another action's default now runs the new one instead
+    for (&key, &default) in &keymap {
+        if key == default {
+            continue;
+        }
+        let taken = keymap_actions().find(|&&(n, d)| d == key && !config.contains_key(n));
+        if let Some((other, _)) = taken {
+            let action = action_name(default).unwrap_or_default();
+            warnings.push(format!(
+                "keymap: {} no longer runs {other}, it's bound to {action}",
+                key_label(key)
+            ));
+        }
+    }
+    (keymap, warnings)
+}
+
+fn action_name(default: i32) -> Option<&'static str> {
+    keymap_actions().find(|(_, d)| *d == default).map(|(n, _)| *n)
+}
+
+// Extra lines for the ? overlay, one per remapped key
+fn keymap_help(keymap: &HashMap<i32, i32>) -> Vec<(String, String)> {
+    let mut lines: Vec<_> = keymap
+        .iter()
+        .filter(|(key, default)| key != default)
+        .map(|(&key, &default)| {
+            let action = action_name(default).unwrap_or_default();
+            (key_label(key), format!("{action} (keymap)"))
+        })
+        .collect();
+    lines.sort();
+    lines
+}
+
+// Read a : command and carry it out. Commands that stand for a key come back
+// as that key for the main loop to handle.
+fn run_palette(state: &mut AppState) -> Option<i32> {
+    let line = prompt_input_with(":", "", complete_palette)?;
+    if line.trim().is_empty() {
+        return None;
+    }
+    match parse_palette(&line) {
+        Ok(PaletteCommand::Key(key)) => return Some(key),
+        Ok(PaletteCommand::Tags(_) | PaletteCommand::Retag { .. }) if state.read_only => {
+            flash_error("Not allowed in read-only mode");
+        }
+        Ok(PaletteCommand::Tags(tags)) => {
+            let i = current_index(state)?;
+            match set_tags(&mut state.projects[i], tags) {
+                Ok(()) => {
+                    flash_ok("Tags updated");
+                    rebuild_filter(state);
+                }
+                Err(e) => flash_error(&format!("Edit tags failed: {e:#}")),
+            }
+        }
+        Ok(PaletteCommand::Retag { add, tags }) => retag_filtered(state, &tags, add),
+        Ok(PaletteCommand::Sort(mode)) => {
+            let selected_path = current_project(state).map(|p| p.path.clone());
+            state.sort = mode;
+            rebuild_filter(state);
+            if let Some(path) = selected_path {
+                select_path(state, &path);
+            }
+        }
+        Err(e) => flash_error(&e),
+    }
+    None
+}
+
+// Only an exact "YES" confirms
+fn prompt_confirm(question: &str) -> bool {
+    prompt_input(&format!("{question} type YES: "), "").as_deref() == Some("YES")
+}
+
+fn delete_project(p: &Project) -> Result<()> {
+    fs::remove_dir_all(&p.path).with_context(|| format!("Removing {}", p.path.display()))
+}
+
+// Check that `name` is a single plain directory name directly inside `root`,
+// returning the path it would get
+fn validate_project_name(root: &Path, name: &str) -> Result<PathBuf> {
+    if name.trim().is_empty() {
+        return Err(anyhow::anyhow!("Name cannot be empty"));
+    }
+    if name != name.trim() {
+        return Err(anyhow::anyhow!("Name cannot start or end with whitespace"));
+    }
+    if name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+        return Err(anyhow::anyhow!("Name cannot contain path separators"));
+    }
+    if name.starts_with('.') {
+        return Err(anyhow::anyhow!("Name cannot start with a dot"));
+    }
+    if name.chars().any(|c| c.is_control()) {
+        return Err(anyhow::anyhow!("Name cannot contain control characters"));
+    }
+    let real_root = root
+        .canonicalize()
+        .with_context(|| format!("Resolving {}", root.display()))?;
+    if real_root.join(name).parent() != Some(real_root.as_path()) {
+        return Err(anyhow::anyhow!("{name} would be outside {}", root.display()));
+    }
+    Ok(root.join(name))
+}
+
+// Move the directory within its root and keep the config name in sync.
+// Returns the new path.
+fn rename_project(p: &Project, new_name: &str) -> Result<PathBuf> {
+    let target = validate_project_name(&p.root, new_name)?;
+    if target.exists() {
+        return Err(anyhow::anyhow!("{} already exists", target.display()));
+    }
+    fs::rename(&p.path, &target)?;
+    if let Some(cfg) = &p.cfg {
+        let mut cfg = cfg.clone();
+        cfg.name = new_name.to_string();
+        write_config(&target, &cfg)?;
+    }
+    Ok(target)
+}
+
+// Recursive copy that keeps permissions and symlinks, skipping directories named
+// in `exclude` at any depth
+fn copy_dir_all(src: &Path, dst: &Path, exclude: &[String]) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let ty = entry.file_type()?;
+        let to = dst.join(entry.file_name());
+        if ty.is_dir() {
+            if !exclude.iter().any(|x| entry.file_name() == x.as_str()) {
+                copy_dir_all(&entry.path(), &to, exclude)?;
+            }
+        } else if ty.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &to)?;
+        } else {
+            // fs::copy keeps the permission bits
+            fs::copy(entry.path(), &to)
+                .with_context(|| format!("Copying {}", entry.path().display()))?;
+        }
+    }
+    // Last, so a read-only directory can still be filled
+    fs::set_permissions(dst, fs::metadata(src)?.permissions())?;
+    Ok(())
+}
+
+// rename(2) can't cross filesystems, so fall back to copy-then-delete
+fn move_dir(src: &Path, dst: &Path) -> Result<()> {
+    match fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            if let Err(e) = copy_dir_all(src, dst, &[]) {
+                let _ = fs::remove_dir_all(dst);
+                return Err(e);
+            }
+            fs::remove_dir_all(src)?;
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Archived projects move to the archive root; archived ones move back to the first root.
+// Returns the new path.
+fn toggle_archive(p: &Project, archive_root: &Path, first_root: &Path) -> Result<PathBuf> {
+    let name = p.path.file_name().context("Project has no directory name")?;
+    let target_dir = if p.archived { first_root } else { archive_root };
+    fs::create_dir_all(target_dir)
+        .with_context(|| format!("Creating {}", target_dir.display()))?;
+    let target = target_dir.join(name);
+    if target.exists() {
+        return Err(anyhow::anyhow!("{} already exists", target.display()));
+    }
+    move_dir(&p.path, &target)?;
+    Ok(target)
+}
+
+// Built in templates; "rust" runs `cargo init` in place of `git init`
+const BUILTIN_TEMPLATES: &[&str] = &["blank", "rust"];
+
+fn templates_dir() -> Option<PathBuf> {
+    config_dir().map(|d| d.join("templates"))
+}
+
+// Built in templates first, then the directories under templates_dir()
+fn list_templates() -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN_TEMPLATES.iter().map(|t| t.to_string()).collect();
+    let mut user: Vec<String> = templates_dir()
+        .and_then(|d| fs::read_dir(d).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|n| !names.contains(n))
+        .collect();
+    user.sort();
+    names.append(&mut user);
+    names
+}
+
+// Copy a template tree into `dst`, replacing {{name}} in text files. A .git
+// directory inside the template is left behind.
+fn copy_template(src: &Path, dst: &Path, name: &str) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let ty = entry.file_type()?;
+        let to = dst.join(entry.file_name());
+        if ty.is_dir() {
+            copy_template(&entry.path(), &to, name)?;
+        } else if ty.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &to)?;
+        } else {
+            let bytes = fs::read(entry.path())?;
+            match String::from_utf8(bytes) {
+                Ok(text) => fs::write(&to, text.replace("{{name}}", name))?,
+                // Binary files go over untouched
+                Err(e) => fs::write(&to, e.into_bytes())?,
+            }
+        }
+    }
+    Ok(())
+}
+
+// Copy `p` to ROOT/<name> as a fresh project: its own git repository, and its
+// config under the new name with a new creation time
+fn duplicate_project(root: &Path, p: &Project, name: &str, exclude: &[String]) -> Result<PathBuf> {
+    let dir = validate_project_name(root, name)?;
+    if dir.exists() {
+        return Err(anyhow::anyhow!("{} already exists", tilde_path(&dir)));
+    }
+    let res = (|| {
+        copy_dir_all(&p.path, &dir, exclude)?;
+        run_init(git_command().arg("init").arg(&dir), "git init")?;
+        let cfg = match &p.cfg {
+            Some(cfg) => ProjectConfig {
+                name: name.to_string(),
+                created_at: Utc::now().to_rfc3339(),
+                // The copy isn't that repository
+                repo_url: None,
+                ..cfg.clone()
+            },
+            None => default_config(&dir),
+        };
+        write_config(&dir, &cfg)
+    })();
+    if let Err(e) = res {
+        // Don't leave a half-made copy behind
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(dir)
+}
+
+fn run_init(cmd: &mut Command, what: &str) -> Result<()> {
+    let out = cmd.output().with_context(|| format!("Failed to run {what}"))?;
+    if !out.status.success() {
+        return Err(anyhow::anyhow!(
+            "{what} failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+fn create_new_project(root: &Path, name: &str, template: &str) -> Result<()> {
+    let dir = validate_project_name(root, name)?;
+    if dir.exists() {
+        return Err(anyhow::anyhow!("Directory already exists"));
+    }
+    let template_src = templates_dir().map(|d| d.join(template)).filter(|d| d.is_dir());
+    if template_src.is_none() && !BUILTIN_TEMPLATES.contains(&template) {
+        return Err(anyhow::anyhow!("Unknown template {template}"));
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("Creating {}", dir.display()))?;
+    let res = scaffold_project(&dir, name, template, template_src.as_deref());
+    if res.is_err() {
+        // Don't leave a half-made project behind
+        let _ = fs::remove_dir_all(&dir);
+    }
+    res
+}
+
+fn scaffold_project(dir: &Path, name: &str, template: &str, src: Option<&Path>) -> Result<()> {
+    if template == "rust" {
+        // cargo init sets up the git repository (and .gitignore) too
+        run_init(Command::new("cargo").args(["init", "--vcs", "git"]).arg(dir), "cargo init")?;
+    } else {
+        run_init(git_command().arg("init").arg(dir), "git init")?;
+    }
+    if let Some(src) = src {
+        copy_template(src, dir, name)
+            .with_context(|| format!("Copying template {}", src.display()))?;
+    }
+    write_default_config(dir)
+}
+
+// Directory name git would pick for the URL: the last path segment without .git,
+// also for scp-like URLs (git@host:user/repo.git)
+fn clone_dir_name(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    url.rsplit(['/', ':']).next().unwrap_or_default().to_string()
+}
+
+// Clone into ROOT/<name> with git's output on the terminal; on failure the error is
+// git's last stderr line and nothing is left behind
+fn clone_project(root: &Path, url: &str) -> Result<PathBuf> {
+    let name = clone_dir_name(url);
+    let dir = validate_project_name(root, &name)
+        .with_context(|| format!("Can't derive a directory name from {url}"))?;
+    if dir.exists() {
+        return Err(anyhow::anyhow!("{} already exists", dir.display()));
+    }
+    endwin();
+    println!("$ git clone {url} {}", dir.display());
+    let res = run_clone(url, &dir);
+    refresh();
+    let res = res.and_then(|_| {
+        let cfg = ProjectConfig { repo_url: Some(url.to_string()), ..default_config(&dir) };
+        write_config(&dir, &cfg)
+    });
+    if let Err(e) = res {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(e);
+    }
+    Ok(dir)
+}
+
+fn run_clone(url: &str, dir: &Path) -> Result<()> {
+    let mut child = git_command()
+        .args(["clone", "--progress", "--", url])
+        .arg(dir)
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run git clone")?;
+    // Pass stderr through so the progress stays visible, keeping the last line for
+    // the error message
+    let mut last = String::new();
+    if let Some(stderr) = child.stderr.take() {
+        let mut out = std::io::stderr();
+        for line in BufReader::new(stderr).split(b'\n').map_while(|l| l.ok()) {
+            let _ = out.write_all(&line);
+            let _ = out.write_all(b"\n");
+            let line = String::from_utf8_lossy(&line);
+            if let Some(text) = line.rsplit('\r').find(|t| !t.trim().is_empty()) {
+                last = text.trim().to_string();
+            }
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("git clone failed: {last}"));
+    }
+    Ok(())
+}
+
+// Marker files and the tag they imply; only checked for existence
+const TAG_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "rust"),
+    ("package.json", "node"),
+    ("go.mod", "go"),
+    ("pyproject.toml", "python"),
+    ("requirements.txt", "python"),
+    ("Dockerfile", "docker"),
+];
+
+// Starting tags for a fresh config, guessed from what's in the directory
+fn detect_tags(dir: &Path) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for (file, tag) in TAG_MARKERS {
+        if dir.join(file).exists() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+    }
+    tags
+}
+
+fn write_default_config(dir: &Path) -> Result<()> {
+    write_config(dir, &default_config(dir))
+}
+
+fn default_config(dir: &Path) -> ProjectConfig {
+    ProjectConfig {
+        name: dir_name(dir),
+        description: String::new(), 
+        tags: detect_tags(dir),
+        created_at: Utc::now().to_rfc3339(),
+        language: None,
+        repo_url: None,
+        commands: Vec::new(),
+    }
+}
+
+fn write_config(dir: &Path, cfg: &ProjectConfig) -> Result<()> {
+    let p = dir.join(CONFIG_NAME);
+    let s = serde_json::to_string_pretty(cfg)?;
+    fs::write(p, s)?;
+    Ok(())
+}
+
+const USAGE: &str =
+    "usage: fylex [--read-only] [--list [--plain] | open <query> | export <file> | import <file>]";
+
+// Keys that change projects on disk (or run their commands); refused with --read-only
+const MUTATING_KEYS: &[i32] = &[
+    33,  // ! project commands
+    43,  // + duplicate
+    66,  // B tarball
+    67,  // C commit
+    68,  // D description
+    69,  // E name
+    70,  // F edit config
+    76,  // L clone
+    77,  // M rename
+    78,  // N new project
+    80,  // P stash pop
+    83,  // S stash
+    84,  // T tags
+    88,  // X delete
+    90,  // Z archive
+    97,  // a add config
+    98,  // b build
+    100, // d description
+    101, // e name
+    109, // m rename
+    110, // n notes
+    112, // p pull
+    116, // t tags
+];
+
+// Non-interactive entry point; returns the process exit code
+fn run_cli(state: &mut AppState, args: &[String], skipped: &[String]) -> i32 {
+    let code = match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["--list"] => print_list(state, false),
+        ["--list", "--plain"] | ["--plain", "--list"] => print_list(state, true),
+        ["open", query @ ..] if !query.is_empty() => open_by_query(state, &query.join(" ")),
+        ["export", file] => report(export_configs(state, Path::new(file))),
+        ["import", _] if state.read_only => {
+            eprintln!("fylex: import is not allowed in read-only mode");
+            1
+        }
+        ["import", file] => report(import_configs(Path::new(file))),
+        ["-h"] | ["--help"] => {
+            println!("{USAGE}");
+            0
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return 2;
+        }
+    };
+    for msg in skipped {
+        eprintln!("fylex: {msg}");
+    }
+    if skipped.is_empty() { code } else { 1 }
+}
+
+fn report(res: Result<String>) -> i32 {
+    match res {
+        Ok(msg) => {
+            println!("{msg}");
+            0
+        }
+        Err(e) => {
+            eprintln!("fylex: {e:#}");
+            1
+        }
+    }
+}
+
+// Every project's config in one JSON object keyed by path; unconfigured ones map to null
+fn export_configs(state: &AppState, file: &Path) -> Result<String> {
+    let all: std::collections::BTreeMap<&Path, Option<&ProjectConfig>> = state
+        .projects
+        .iter()
+        .map(|p| (p.path.as_path(), p.cfg.as_ref()))
+        .collect();
+    fs::write(file, serde_json::to_string_pretty(&all)?)
+        .with_context(|| format!("Writing {}", file.display()))?;
+    Ok(format!("Exported {} projects to {}", all.len(), file.display()))
+}
+
+// Write the configs from an export back into their directories. Nulls, and
+// directories that no longer exist, are skipped.
+fn import_configs(file: &Path) -> Result<String> {
+    let text = fs::read_to_string(file).with_context(|| format!("Reading {}", file.display()))?;
+    let all: std::collections::BTreeMap<PathBuf, Option<serde_json::Value>> =
+        serde_json::from_str(&text)
+            .with_context(|| format!("{} is not a fylex export", file.display()))?;
+    // Check every entry before writing anything
+    let mut configs = Vec::new();
+    for (dir, value) in all {
+        let Some(value) = value else {
+            continue;
+        };
+        let cfg: ProjectConfig = serde_json::from_value(value)
+            .with_context(|| format!("Invalid config for {}", dir.display()))?;
+        configs.push((dir, cfg));
+    }
+    let mut restored = 0;
+    let mut skipped = 0;
+    for (dir, cfg) in configs {
+        if !dir.is_dir() {
+            eprintln!("fylex: skipping {}: no such directory", dir.display());
+            skipped += 1;
+            continue;
+        }
+        write_config(&dir, &cfg).with_context(|| format!("Writing {}", dir.display()))?;
+        restored += 1;
+    }
+    Ok(format!("Restored {restored} configs ({skipped} skipped)"))
+}
+
+// Print the path of the project `query` picks out, for `cd "$(fylex open foo)"`.
+// Uses the same matching as the interactive filter; an exact name wins outright.
+fn open_by_query(state: &AppState, query: &str) -> i32 {
+    let tokens = parse_filter(&query.to_lowercase());
+    let mut scored: Vec<_> = state
+        .filtered
+        .iter()
+        .map(|&i| &state.projects[i])
+        .filter_map(|p| project_score(&tokens, p, false).map(|score| (p, score)))
+        .collect();
+    let exact: Vec<_> = scored
+        .iter()
+        .filter(|(p, _)| display_name(p).eq_ignore_ascii_case(query))
+        .cloned()
+        .collect();
+    if !exact.is_empty() {
+        scored = exact;
+    }
+    let Some(best) = scored.iter().map(|(_, score)| *score).max() else {
+        eprintln!("fylex: no project matches {query:?}");
+        return 1;
+    };
+    let top: Vec<_> = scored.iter().filter(|(_, score)| *score == best).collect();
+    if let [(p, _)] = top.as_slice() {
+        println!("{}", p.path.display());
+        return 0;
+    }
+    eprintln!("fylex: {query:?} is ambiguous:");
+    for (p, _) in top {
+        eprintln!("  {}\t{}", display_name(p), p.path.display());
+    }
+    1
+}
+
+// Block until every project has its git info
+fn wait_for_git(state: &mut AppState) {
+    while state.git_rx.is_some() {
+        poll_git_updates(state);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
+
+fn git_state_name(state: Option<u8>) -> Option<&'static str> {
+    match state {
+        Some(1) => Some("clean"),
+        Some(2) => Some("modified"),
+        Some(3) => Some("submodule-modified"),
+        Some(4) => Some("unknown"),
+        _ => None,
+    }
+}
+
+// --list prints JSON, --plain one tab-separated line per project (for fzf and friends)
+fn print_list(state: &mut AppState, plain: bool) -> i32 {
+    wait_for_git(state);
+    let mut projects = state.filtered.iter().map(|&i| &state.projects[i]);
+    let mut out = std::io::stdout().lock();
+    let res = if plain {
+        projects.try_for_each(|p| {
+            let tags = p.cfg.as_ref().map(|c| c.tags.join(",")).unwrap_or_default();
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}",
+                display_name(p),
+                p.path.display(),
+                tags,
+                git_state_name(p.git_state).unwrap_or("-"),
+                p.branch.as_deref().unwrap_or("-")
+            )
+        })
+    } else {
+        let list: Vec<_> = projects
+            .map(|p| {
+                serde_json::json!({
+                    "name": display_name(p),
+                    "path": p.path,
+                    "tags": p.cfg.as_ref().map(|c| c.tags.clone()).unwrap_or_default(),
+                    "git_state": git_state_name(p.git_state),
+                    "branch": p.branch,
+                })
+            })
+            .collect();
+        serde_json::to_string_pretty(&list)
+            .map_err(std::io::Error::other)
+            .and_then(|s| writeln!(out, "{s}"))
+    };
+    match res {
+        Ok(()) => 0,
+        // A closed pipe (`fylex --list | head`) isn't worth complaining about
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            eprintln!("fylex: {e}");
+            1
+        }
+    }
+}
+
+// Color pair, theme.toml key and default colors for each role
+const THEME_ROLES: &[(i16, &str, i16, i16)] = &[
+    (1, "header", COLOR_WHITE, COLOR_BLUE),
+    (2, "selected", COLOR_YELLOW, COLOR_BLACK),
+    (3, "labels", COLOR_CYAN, COLOR_BLACK),
+    (4, "ok", COLOR_GREEN, COLOR_BLACK),
+    (5, "warn", COLOR_RED, COLOR_BLACK),
+    (6, "unconfigured", COLOR_WHITE, COLOR_BLACK),
+];
+
+// A color name, "default" for the terminal's own, or a palette index the terminal has
+fn parse_color(v: &toml::Value) -> Option<i16> {
+    match v {
+        toml::Value::String(name) => match name.to_lowercase().as_str() {
+            "default" => Some(-1),
+            "black" => Some(COLOR_BLACK),
+            "red" => Some(COLOR_RED),
+            "green" => Some(COLOR_GREEN),
+            "yellow" => Some(COLOR_YELLOW),
+            "blue" => Some(COLOR_BLUE),
+            "magenta" => Some(COLOR_MAGENTA),
+            "cyan" => Some(COLOR_CYAN),
+            "white" => Some(COLOR_WHITE),
+            _ => None,
+        },
+        toml::Value::Integer(i) if (0..COLORS() as i64).contains(i) => Some(*i as i16),
+        _ => None,
+    }
+}
+
+fn read_theme(path: &Path) -> Result<toml::Table> {
+    Ok(fs::read_to_string(path)?.parse()?)
+}
 
+// Set up the color pairs from ~/.config/fylex/theme.toml. Each role takes a color
+// (`ok = "green"`) or a table (`header = { fg = "white", bg = 24 }`); anything
+// missing or invalid keeps its default, and the problems are returned as warnings.
+fn apply_theme() -> Vec<String> {
+    let mut warnings = Vec::new();
+    let path = config_dir().map(|d| d.join("theme.toml")).filter(|p| p.exists());
+    let theme = match path.map(|p| read_theme(&p)) {
+        Some(Ok(theme)) => theme,
+        Some(Err(e)) => {
+            warnings.push(format!("theme.toml ignored: {e:#}"));
+            toml::Table::new()
+        }
+        None => toml::Table::new(),
+    };
+
+    for (key, _) in &theme {
+        if !THEME_ROLES.iter().any(|(_, role, _, _)| role == key) {
+            warnings.push(format!("theme.toml: unknown role {key}"));
+        }
+    }
+    for &(pair, role, default_fg, default_bg) in THEME_ROLES {
+        let (mut fg, mut bg) = (default_fg, default_bg);
+        let mut set = |slot: &mut i16, name: &str, v: Option<&toml::Value>| {
+            if let Some(v) = v {
+                match parse_color(v) {
+                    Some(c) => *slot = c,
+                    None => warnings.push(format!("theme.toml: invalid color {v} for {name}")),
+                }
+            }
+        };
+        match theme.get(role) {
+            Some(toml::Value::Table(t)) => {
+                set(&mut fg, &format!("{role}.fg"), t.get("fg"));
+                set(&mut bg, &format!("{role}.bg"), t.get("bg"));
+            }
+            other => set(&mut fg, role, other),
         }
+        init_pair(pair, fg, bg);
     }
-    buf
-
+    warnings
 }
 
-fn create_new_project(root: &str, name: &str) -> Result<()> {
-    let dir = Path::new(root).join(name);
-    if dir.exists() {
-        return Err(anyhow::anyhow!("Directory already exists"));
+// Puts the terminal back however main exits: early returns drop the guard, and
+// panics restore it before the message is printed so it stays readable
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn install() -> Self {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if !isendwin() {
+                endwin();
+            }
+            default_hook(info);
+        }));
+        TerminalGuard
     }
-    let _ = fs::create_dir_all(&dir);
-    // Adicionar git init
-    Command::new("git")
-        .arg("init")
-        .arg(&dir)
-        .output()
-        .with_context(|| "Failed to initialize git repository")?;
-    write_default_config(&dir)
 }
 
-fn write_default_config(dir: &Path) -> Result<()> {
-    let name = Path::new(dir)
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .to_string();
-    let cfg = ProjectConfig {
-        name,
-        description: String::new(), 
-        tags: Vec::new(),
-        created_at: Utc::now().to_rfc3339(),
+// Very first launch (no config.toml, no FYLEX_ROOT): ask where the projects live
+// and write that down as `root`. Enter takes DEFAULT_ROOT, Ctrl+D skips the
+// whole thing and nothing is written.
+fn first_run_setup() {
+    use std::io::IsTerminal;
+    let Some(config) = app_config_path().filter(|p| !p.exists()) else {
+        return;
+    };
+    let root_env = std::env::var("FYLEX_ROOT").is_ok_and(|r| !r.trim().is_empty());
+    if root_env || !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return;
+    }
+    eprintln!("Welcome to fylex! There's no {} yet.", tilde_path(&config));
+    eprint!("Where do you keep your projects? [{DEFAULT_ROOT}] ");
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => {
+            eprintln!();
+            return;
+        }
+        Ok(_) => {}
+    }
+    let root = match answer.trim() {
+        "" => DEFAULT_ROOT,
+        r => r,
     };
+    let dir = expand_tilde(root);
+    if !dir.is_dir() && !create_missing_root(&dir) {
+        eprintln!("fylex: create {root} (or pick another folder) and run fylex again");
+        std::process::exit(1);
+    }
+    match save_app_setting("root", root.into()) {
+        Ok(()) => eprintln!("Saved to {}, more settings can go there", tilde_path(&config)),
+        Err(e) => eprintln!("fylex: {e:#}"),
+    }
+}
 
-    write_config(dir, &cfg)
+// First run without any project folder: offer to create the root, when there's
+// someone at the terminal to ask
+fn create_missing_root(root: &Path) -> bool {
+    use std::io::IsTerminal;
+    if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+        return false;
+    }
+    eprint!("fylex: project root {} does not exist. Create it? [y/N] ", tilde_path(root));
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err()
+        || !matches!(answer.trim(), "y" | "Y" | "yes")
+    {
+        return false;
+    }
+    match fs::create_dir_all(root) {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("fylex: creating {} failed: {e}", root.display());
+            false
+        }
+    }
 }
 
-fn write_config(dir: &Path, cfg: &ProjectConfig) -> Result<()> {
-    let p = dir.join(CONFIG_NAME);
-    let s = serde_json::to_string_pretty(cfg)?;
-    fs::write(p, s)?;
-    Ok(())
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if !isendwin() {
+            endwin();
+        }
+    }
 }
 
 fn main() -> Result<()> {
-    let mut state = AppState::default();
-    state.projects = scan_projects(ROOT)?;
+    // Only for the interactive list, not for scripted --list / open / export runs
+    if std::env::args().skip(1).all(|a| a == "--read-only") {
+        first_run_setup();
+    }
+    let app_cfg = match read_app_config() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("fylex: {e:#}");
+            std::process::exit(1);
+        }
+    };
+    let roots = resolve_roots(&app_cfg);
+    let archive_root = resolve_archive_root(&roots);
+    if !roots.iter().any(|r| r.exists()) && !create_missing_root(&roots[0]) {
+        eprintln!("fylex: project root {} does not exist", tilde_path(&roots[0]));
+        eprintln!("Create it, or point fylex at your projects with FYLEX_ROOT=~/code");
+        eprintln!("or `root = \"~/code\"` in ~/.config/fylex/config.toml");
+        std::process::exit(1);
+    }
+    let (ignore_globs, ignore_warnings) = load_ignore_file();
+    let opts = ScanOptions::new(&app_cfg, false, &ignore_globs);
+    let (projects, skipped) = match scan_projects(&roots, None, &opts) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("fylex: cannot read any project root: {e:#}");
+            eprintln!("Set FYLEX_ROOT or `root = \"...\"` in ~/.config/fylex/config.toml");
+            std::process::exit(1);
+        }
+    };
+    let _ = GIT_SETTINGS.set(GitSettings {
+        program: app_cfg.git.clone(),
+        timeout: (app_cfg.git_timeout > 0)
+            .then(|| std::time::Duration::from_secs(app_cfg.git_timeout)),
+    });
+    let list_ratio = app_cfg.list_ratio.clamp(0.20, 0.80);
+    let mut state = AppState {
+        list_ratio,
+        expanded_rows: app_cfg.expanded_rows,
+        config: app_cfg,
+        roots,
+        archive_root,
+        projects,
+        ignore_globs,
+        ..Default::default()
+    };
+    state.git_cache = load_git_cache();
+    state.usage = load_usage();
+    state.pinned = load_favorites();
+    start_git_scan(&mut state, false);
     rebuild_filter(&mut state);
 
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(pos) = args.iter().position(|a| a == "--read-only") {
+        args.remove(pos);
+        state.read_only = true;
+    }
+    if !args.is_empty() {
+        for msg in &ignore_warnings {
+            eprintln!("fylex: {msg}");
+        }
+        std::process::exit(run_cli(&mut state, &args, &skipped));
+    }
+
+    if state.config.restore_session {
+        restore_session(&mut state);
+    }
+    let mut watch_error = None;
+    if state.config.watch
+        && let Err(e) = start_watch(&mut state)
+    {
+        watch_error = Some(format!("Watch failed: {e:#}"));
+    }
+
     // ncurses init
+    let _ = setlocale(LcCategory::all, "");
     initscr();
+    let _terminal = TerminalGuard::install();
     raw();
     keypad(stdscr(), true);
+    // Keep ESC responsive; the default delay waits a full second for escape sequences
+    set_escdelay(25);
     noecho();
     curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+    if state.config.mouse {
+        let events = BUTTON1_CLICKED | BUTTON1_DOUBLE_CLICKED | BUTTON4_PRESSED | BUTTON5_PRESSED;
+        mousemask(events as mmask_t, None);
+    }
 
+    let mut theme_warnings = Vec::new();
     if has_colors() {
         start_color();
         // Allow terminal default background/foreground if supported
         let _ = use_default_colors();
-        init_pair(1, COLOR_WHITE, COLOR_BLUE); // header
-        init_pair(2, COLOR_YELLOW, COLOR_BLACK); // selected
-        init_pair(3, COLOR_CYAN, COLOR_BLACK); // labels
-        init_pair(4, COLOR_GREEN, COLOR_BLACK); // ok
-        init_pair(5, COLOR_RED, COLOR_BLACK); // warn
+        theme_warnings = apply_theme();
+        if COLORS() >= 256 {
+            init_pair(7, 114, COLOR_BLACK); // clean
+            init_pair(8, 209, COLOR_BLACK); // modified
+            init_pair(9, 75, COLOR_BLACK); // ahead
+            init_pair(10, 180, COLOR_BLACK); // behind
+            state.rich_colors = true;
+        }
+    }
+
+    let (keymap, keymap_warnings) = build_keymap(&state.config.keymap);
+    state.keymap = keymap;
+
+    let warnings = theme_warnings.iter().chain(&keymap_warnings).chain(&ignore_warnings);
+    for msg in warnings.chain(&skipped).chain(&watch_error) {
+        draw(&state);
+        flash_error(msg);
     }
 
     loop {
+        poll_git_updates(&mut state);
+        ensure_visible(&mut state);
+        refresh_readme(&mut state);
+        refresh_size(&mut state);
+        poll_size(&mut state);
+        refresh_commits(&mut state);
+        refresh_stash(&mut state);
+        refresh_notes(&mut state);
+        refresh_visible_git(&mut state);
+        poll_fetches(&mut state);
+        poll_jobs(&mut state);
+        poll_watch(&mut state);
         draw(&state);
+        // Wake up periodically while background results are still coming in
+        let waiting = state.git_rx.is_some()
+            || state.size_rx.is_some()
+            || !state.jobs.is_empty()
+            || !state.fetches.is_empty();
+        if waiting {
+            state.spinner_frame = state.spinner_frame.wrapping_add(1);
+        }
+        let mut wait = if waiting {
+            100
+        } else if state.watch.is_some() {
+            // Watch events arrive on another thread, so getch has to come back to check
+            250
+        } else {
+            -1
+        };
+        // Wake up for the footer clock
+        let clock = ms_to_next_minute();
+        wait = if wait < 0 { clock } else { wait.min(clock) };
+        // Come back in time for the next periodic refresh
+        if let Some(interval) = refresh_interval(&state.config) {
+            let since = state.last_refresh.map_or(interval, |t| t.elapsed());
+            let due = interval.saturating_sub(since).as_millis().max(1) as i32;
+            wait = if wait < 0 { due } else { wait.min(due) };
+        }
+        timeout(wait);
 
-        let ch = getch();
+        let mut ch = getch();
+        if ch == KEY_MOUSE {
+            ch = mouse_key(&mut state);
+        }
+        if ch == KEY_RESIZE {
+            handle_resize(&mut state);
+            continue;
+        }
+        // j/k and the page keys scroll the help overlay, any other real key closes it
+        if state.show_help {
+            let total = KEYBINDINGS.len() + keymap_help(&state.keymap).len();
+            let page = help_page(LINES(), total);
+            let max = total - page;
+            match ch {
+                ERR => {}
+                106 | KEY_DOWN => state.help_scroll = (state.help_scroll + 1).min(max),
+                107 | KEY_UP => state.help_scroll = state.help_scroll.saturating_sub(1),
+                KEY_NPAGE | 32 => state.help_scroll = (state.help_scroll + page).min(max),
+                KEY_PPAGE => state.help_scroll = state.help_scroll.saturating_sub(page),
+                _ => state.show_help = false,
+            }
+            continue;
+        }
+        // Raw mode delivers Ctrl+C as a key rather than a signal
+        if ch == 3 {
+            break;
+        }
+        // Ctrl+S toggles case-sensitive matching, also while typing the filter
+        if ch == 19 {
+            state.case_sensitive = !state.case_sensitive;
+            rebuild_filter(&mut state);
+            // Best match is on top, as after typing
+            state.selected = 0;
+            continue;
+        }
+        if state.mode == Mode::Filter {
+            handle_filter_key(&mut state, ch);
+            continue;
+        }
+        // After #, 1-9 filters by that tag of the selection; any other key cancels
+        // (# and Esc do nothing else)
+        if state.tag_pick && ch != ERR {
+            state.tag_pick = false;
+            if let 49..=57 = ch {
+                filter_by_tag(&mut state, (ch - 49) as usize);
+                continue;
+            }
+            if ch == 35 || ch == 27 {
+                continue;
+            }
+        }
+        if let Some(&key) = state.keymap.get(&ch) {
+            ch = key;
+        }
+        // : for the command palette
+        if ch == 58 {
+            match run_palette(&mut state) {
+                Some(key) => ch = key,
+                None => continue,
+            }
+        }
+        if state.read_only && MUTATING_KEYS.contains(&ch) {
+            flash_error("Not allowed in read-only mode");
+            continue;
+        }
         match ch {
-            81 => break,
-            // Allow filter text input
-            // Backspace
-            127 | KEY_BACKSPACE => {
-                state.filter_text.pop();
+            81 | 113 => break,
+            // l for keeping the details on this project while moving around
+            108 => {
+                state.locked = match state.locked {
+                    Some(_) => None,
+                    None => state.filtered.get(state.selected).copied(),
+                };
+            }
+            // i for branch and tags in the list rows
+            105 => state.expanded_rows = !state.expanded_rows,
+            // < / > for resizing the list pane
+            60 => adjust_list_ratio(&mut state, -0.05),
+            62 => adjust_list_ratio(&mut state, 0.05),
+            // ? for the keybinding overlay
+            63 => {
+                state.show_help = true;
+                state.help_scroll = 0;
+            }
+            // / enters filter mode
+            47 => state.mode = Mode::Filter,
+            // Esc unlocks the details, then clears the filter, then the marks
+            27 if state.locked.is_some() => state.locked = None,
+            27 if !state.filter_text.is_empty() => {
+                state.filter_text.clear();
                 rebuild_filter(&mut state);
+                state.selected = 0;
+                state.scroll_top = 0;
             }
-            KEY_UP => {
-                if state.selected > 0 {
-                    state.selected -= 1;
-                }
+            27 => state.marked.clear(),
+            KEY_UP if state.selected > 0 => {
+                state.selected -= 1;
             }
-            KEY_DOWN => {
-                if state.selected + 1 < state.filtered.len() {
-                    state.selected += 1;
-                }
+            KEY_DOWN if state.selected + 1 < state.filtered.len() => {
+                state.selected += 1;
+            }
+            KEY_PPAGE => page_move(&mut state, false),
+            KEY_NPAGE => page_move(&mut state, true),
+            // Vim-style movement
+            107 => {
+                state.selected = state.selected.saturating_sub(1);
+            }
+            106 => {
+                state.selected = (state.selected + 1).min(state.filtered.len().saturating_sub(1));
+            }
+            103 => {
+                state.selected = 0;
+            }
+            71 => {
+                state.selected = state.filtered.len().saturating_sub(1);
             }
             // Enter walk into project folder through terminal
             10 | KEY_ENTER => {
-                if let Some(p) = current_project(&state) {
+                if let Some(p) = current_project(&state).cloned() {
+                    record_open(&mut state, &p.path);
+                    // Both the shell and tmux outside tmux exec and won't come back
+                    let _ = persist_settings(&state);
+                    let use_tmux = state.config.tmux || std::env::var_os("TMUX").is_some();
+                    if use_tmux && tmux_available() {
+                        if let Err(e) = open_in_tmux(&p.path, &display_name(&p)) {
+                            flash_error(&format!("tmux failed: {e:#}"));
+                        }
+                        continue;
+                    }
+                    if use_tmux {
+                        flash_error("tmux not found, opening a plain shell");
+                    }
                     match open_in_terminal(&p.path) {
                         Ok(_) => {
                             break;
                         }
-                        Err(e) => flash_error(&format!("Terminal open failed: {e}")),
+                        Err(e) => flash_error(&format!("Terminal open failed: {e:#}")),
+                    }
+                }
+            }
+            // O for open project folder in the file manager
+            79 | 111 => {
+                if let Some(p) = current_project(&state)
+                    && let Err(e) = open_in_file_manager(&p.path)
+                {
+                    flash_error(&format!("Open folder failed: {e:#}"));
+                }
+            }
+            // u for the repository's web page
+            117 => {
+                if let Some(p) = current_project(&state) {
+                    match repo_web_url(p) {
+                        Some(url) => {
+                            if let Err(e) = open_in_browser(&url) {
+                                flash_error(&format!("Open in browser failed: {e:#}"));
+                            }
+                        }
+                        None => flash_error("No repo_url in the config and no hosted origin remote"),
+                    }
+                }
+            }
+            // c for opening the project in $VISUAL / $EDITOR
+            99 => {
+                if let Some(p) = current_project(&state).cloned() {
+                    record_open(&mut state, &p.path);
+                    if let Err(e) = open_in_editor(&p.path, &state.config.gui_editors) {
+                        flash_error(&format!("Editor failed: {e:#}"));
+                    }
+                }
+            }
+            // F for editing fylex.config.json by hand
+            70 => {
+                if let Some(i) = current_index(&state) {
+                    let path = state.projects[i].path.clone();
+                    let res = edit_config_file(&mut state.projects[i]);
+                    match (res, &state.projects[i].cfg_error) {
+                        (Err(e), _) => flash_error(&format!("Edit failed: {e:#}")),
+                        (Ok(_), Some(e)) => flash_error(&format!("Invalid config: {e}")),
+                        (Ok(_), None) => flash_ok("Config reloaded"),
                     }
+                    rebuild_filter(&mut state);
+                    select_path(&mut state, &path);
+                }
+            }
+            // n for the project's notes
+            110 => {
+                if let Some(p) = current_project(&state)
+                    && let Err(e) = edit_notes(p)
+                {
+                    flash_error(&format!("Notes failed: {e:#}"));
                 }
             }
             // N for create new project folder
             78 => {
-                let name = prompt_input("New project name: ","");
+                let templates = list_templates();
+                let label = format!("Template ({}, empty for blank): ", templates.join(", "));
+                let Some(template) = prompt_input(&label, "") else {
+                    continue;
+                };
+                let template = match template.trim() {
+                    "" => "blank".to_string(),
+                    t => t.to_string(),
+                };
+                let Some(name) = prompt_input("New project name: ","") else {
+                    continue;
+                };
                 if name.trim().is_empty() {
                     flash_error("Name cannot be empty");
                 } else {
-                    match create_new_project(ROOT, name.trim()) {
+                    match create_new_project(&state.roots[0], name.trim(), &template) {
                         Ok(_) => {
                             flash_ok("Project created");
-                            state.projects = scan_projects(ROOT)?;
+                            match reload_projects(&mut state, false) {
+                                Ok(_) => {
+                                    let dir = state.roots[0].join(name.trim());
+                                    select_path(&mut state, &dir);
+                                }
+                                Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                            }
+                        }
+                        Err(e) => flash_error(&format!("Create failed: {e:#}")),
+                    }
+                }
+            }
+            // L for a new project cloned from a git URL
+            76 => {
+                let Some(url) = prompt_input("Clone from git URL: ", "") else {
+                    continue;
+                };
+                if url.trim().is_empty() {
+                    continue;
+                }
+                match clone_project(&state.roots[0], url.trim()) {
+                    Ok(dir) => {
+                        flash_ok("Project cloned");
+                        match reload_projects(&mut state, false) {
+                            Ok(_) => select_path(&mut state, &dir),
+                            Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                        }
+                    }
+                    Err(e) => flash_error(&format!("Clone failed: {e:#}")),
+                }
+            }
+            // + for a new project copied from the selection
+            43 => {
+                let Some(p) = current_project(&state).cloned() else {
+                    continue;
+                };
+                let initial = format!("{}-copy", dir_name(&p.path));
+                let Some(name) = prompt_input("Copy as: ", &initial) else {
+                    continue;
+                };
+                let exclude = state.config.duplicate_exclude.clone();
+                match duplicate_project(&state.roots[0], &p, name.trim(), &exclude) {
+                    Ok(dir) => {
+                        flash_ok(&format!("Copied to {}", tilde_path(&dir)));
+                        match reload_projects(&mut state, false) {
+                            Ok(_) => select_path(&mut state, &dir),
+                            Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                        }
+                    }
+                    Err(e) => flash_error(&format!("Copy failed: {e:#}")),
+                }
+            }
+            // Space for marking / unmarking the project, then moving down
+            32 => {
+                if let Some(path) = current_project(&state).map(|p| p.path.clone()) {
+                    if !state.marked.remove(&path) {
+                        state.marked.insert(path);
+                    }
+                    state.selected = (state.selected + 1).min(state.filtered.len() - 1);
+                }
+            }
+            // a for adding a default config to the marked projects
+            97 if !state.marked.is_empty() => {
+                run_batch(&mut state, "configured", |state, i| {
+                    let p = &mut state.projects[i];
+                    if p.cfg.is_some() {
+                        return Err(anyhow::anyhow!("Project already has a config"));
+                    }
+                    add_default_config(p)
+                });
+            }
+            // a for setting up a config for an unconfigured project, step by step
+            97 => {
+                if let Some(i) = current_index(&state) {
+                    let p = &mut state.projects[i];
+                    if p.cfg.is_some() {
+                        flash_error("Project already has a config");
+                    } else {
+                        match config_wizard(p) {
+                            Ok(true) => {
+                                flash_ok("Config created");
+                                rebuild_filter(&mut state);
+                            }
+                            Ok(false) => {}
+                            Err(e) => flash_error(&format!("Add config failed: {e:#}")),
+                        }
+                    }
+                }
+            }
+            // T for editing the selected project's tags
+            84 | 116 => {
+                if let Some(i) = current_index(&state) {
+                    match edit_tags(&mut state.projects[i]) {
+                        Ok(true) => {
+                            flash_ok("Tags updated");
+                            rebuild_filter(&mut state);
+                        }
+                        Ok(false) => {}
+                        Err(e) => flash_error(&format!("Edit tags failed: {e:#}")),
+                    }
+                }
+            }
+            // E for editing the project name
+            69 | 101 => {
+                if let Some(i) = current_index(&state) {
+                    match edit_name(&mut state.projects[i]) {
+                        Ok(true) => {
+                            flash_ok("Name updated");
                             rebuild_filter(&mut state);
                         }
-                        _ => flash_error("Failed to create project"),
+                        Ok(false) => {}
+                        Err(e) => flash_error(&format!("Edit name failed: {e:#}")),
+                    }
+                }
+            }
+            // D for editing the project description
+            68 | 100 => {
+                if let Some(i) = current_index(&state) {
+                    match edit_description(&mut state.projects[i]) {
+                        Ok(true) => flash_ok("Description updated"),
+                        Ok(false) => {}
+                        Err(e) => flash_error(&format!("Edit description failed: {e:#}")),
+                    }
+                }
+            }
+            // M for renaming (moving) the project directory
+            77 | 109 => {
+                if let Some(p) = current_project(&state)
+                    && let Some(new_name) = prompt_input("Rename directory to: ", &dir_name(&p.path))
+                {
+                    match rename_project(p, new_name.trim()) {
+                        Ok(target) => {
+                            flash_ok("Project renamed");
+                            match reload_projects(&mut state, false) {
+                                Ok(_) => select_path(&mut state, &target),
+                                Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                            }
+                        }
+                        Err(e) => flash_error(&format!("Rename failed: {e:#}")),
+                    }
+                }
+            }
+            // Z for archiving the project, or unarchiving an archived one
+            90 => {
+                if let Some(p) = current_project(&state) {
+                    let was_archived = p.archived;
+                    match toggle_archive(p, &state.archive_root, &state.roots[0]) {
+                        Ok(target) => {
+                            flash_ok(if was_archived {
+                                "Project unarchived"
+                            } else {
+                                "Project archived"
+                            });
+                            match reload_projects(&mut state, false) {
+                                Ok(_) => select_path(&mut state, &target),
+                                Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                            }
+                        }
+                        Err(e) => flash_error(&format!("Archive failed: {e:#}")),
+                    }
+                }
+            }
+            // w for turning watch mode on or off
+            119 => {
+                if state.watch.take().is_some() {
+                    state.watch_pending = WatchPending::default();
+                    flash_ok("Stopped watching for changes");
+                } else {
+                    match start_watch(&mut state) {
+                        Ok(()) => flash_ok("Watching for changes"),
+                        Err(e) => flash_error(&format!("Watch failed: {e:#}")),
+                    }
+                }
+            }
+            // A (shift) for listing archived projects too
+            65 => {
+                state.show_archived = !state.show_archived;
+                match reload_projects(&mut state, false) {
+                    Ok(_) => flash_ok(if state.show_archived {
+                        "Showing archived projects"
+                    } else {
+                        "Hiding archived projects"
+                    }),
+                    Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                }
+            }
+            // . for showing the dot directories hide_dotdirs leaves out
+            46 => {
+                if !state.config.hide_dotdirs {
+                    flash_ok("Dot folders are always shown (hide_dotdirs = false)");
+                    continue;
+                }
+                state.show_hidden = !state.show_hidden;
+                match reload_projects(&mut state, false) {
+                    Ok(_) => flash_ok(if state.show_hidden {
+                        "Showing dot folders"
+                    } else {
+                        "Hiding dot folders"
+                    }),
+                    Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                }
+            }
+            // X for deleting the project directory, after confirmation
+            88 => {
+                if let Some(p) = current_project(&state) {
+                    if !prompt_confirm(&format!("Delete {}?", list_label(&state, p))) {
+                        flash_error("Delete cancelled");
+                    } else {
+                        match delete_project(p) {
+                            Ok(_) => {
+                                flash_ok("Project deleted");
+                                // The cursor stays on the same row, landing on a neighbor
+                                if let Err(e) = reload_projects(&mut state, false) {
+                                    flash_error(&format!("Reload failed: {e:#}"));
+                                }
+                            }
+                            Err(e) => flash_error(&format!("Delete failed: {e:#}")),
+                        }
+                    }
+                }
+            }
+            // J / K for scrolling the README preview
+            74 => scroll_readme(&mut state, true),
+            75 => scroll_readme(&mut state, false),
+            // * for pinning / unpinning the project
+            42 => {
+                if let Some(path) = current_project(&state).map(|p| p.path.clone()) {
+                    if !state.pinned.remove(&path) {
+                        state.pinned.insert(path.clone());
+                    }
+                    if let Err(e) = save_favorites(&state.pinned) {
+                        flash_error(&format!("Saving favorites failed: {e:#}"));
+                    }
+                    rebuild_filter(&mut state);
+                    select_path(&mut state, &path);
+                }
+            }
+            // y for copying the project path to the clipboard
+            121 => {
+                if let Some(p) = current_project(&state) {
+                    let path = p.path.to_string_lossy();
+                    match copy_to_clipboard(&state.config, &path) {
+                        Ok(()) => flash_ok(&format!("Copied {path}")),
+                        Err(e) => flash_error(&format!("Copy failed: {e:#}")),
+                    }
+                }
+            }
+            // ! for the project's own commands
+            33 => {
+                if let Some(p) = current_project(&state) {
+                    let commands = p.cfg.as_ref().map(|c| c.commands.clone()).unwrap_or_default();
+                    if commands.is_empty() {
+                        flash_error("No commands defined for this project");
+                        continue;
+                    }
+                    let items: Vec<String> = commands
+                        .iter()
+                        .map(|c| format!("{}  ({})", c.name, c.command))
+                        .collect();
+                    let title = format!(" Run in {} ", display_name(p));
+                    if let Some(i) = pick_from_menu(&title, &items) {
+                        let c = &commands[i];
+                        match run_shell_command(&p.path, &c.command) {
+                            Ok(s) if s.success() => flash_ok(&format!("{} succeeded", c.name)),
+                            Ok(s) => flash_error(&format!("{} failed: {s}", c.name)),
+                            Err(e) => flash_error(&format!("{} failed: {e:#}", c.name)),
+                        }
+                    }
+                }
+            }
+            // b for running the project's build command
+            98 => {
+                if let Some(p) = current_project(&state) {
+                    let suggested = build_command(&state.config, p).unwrap_or_default();
+                    let label = format!("Run in {}: ", display_name(p));
+                    let command = prompt_input(&label, &suggested).unwrap_or_default();
+                    let command = command.trim();
+                    if !command.is_empty() {
+                        match run_shell_command(&p.path, command) {
+                            Ok(s) if s.success() => flash_ok(&format!("`{command}` succeeded")),
+                            Ok(s) => flash_error(&format!("`{command}` failed: {s}")),
+                            Err(e) => flash_error(&format!("Build failed: {e:#}")),
+                        }
+                    }
+                }
+            }
+            // p for pulling every marked project
+            112 if !state.marked.is_empty() => {
+                run_batch(&mut state, "pulls started", |state, i| {
+                    let p = state.projects[i].clone();
+                    pull_project(state, &p)
+                });
+            }
+            // p for git pull --ff-only in the background
+            112 => {
+                if let Some(p) = current_project(&state).cloned() {
+                    match pull_project(&mut state, &p) {
+                        Ok(()) => flash_ok(&format!("Pulling {}…", display_name(&p))),
+                        Err(e) => flash_error(&format!("Pull failed: {e:#}")),
+                    }
+                }
+            }
+            // C (shift) for committing all tracked changes
+            67 => {
+                if let Some(i) = current_index(&state)
+                    && let Some(msg) = prompt_input("Commit message: ", "")
+                {
+                    if msg.trim().is_empty() {
+                        flash_error("Commit message cannot be empty");
+                    } else {
+                        match commit_project(&mut state.projects[i], msg.trim()) {
+                            Ok(summary) => flash_ok(&summary),
+                            Err(e) => flash_error(&format!("Commit failed: {e:#}")),
+                        }
+                    }
+                }
+            }
+            // S for stashing the changes
+            83 => {
+                if let Some(p) = current_project(&state).cloned()
+                    && let Err(e) = stash_project(&mut state, &p)
+                {
+                    flash_error(&format!("Stash failed: {e:#}"));
+                }
+            }
+            // P for popping the latest stash
+            80 => {
+                if let Some(p) = current_project(&state).cloned()
+                    && let Err(e) = stash_pop_project(&mut state, &p)
+                {
+                    flash_error(&format!("Stash pop failed: {e:#}"));
+                }
+            }
+            // v / V for paging through the unstaged / staged changes
+            118 | 86 => {
+                let staged = ch == 86;
+                if let Some(p) = current_project(&state) {
+                    match p.git_state.map(|_| show_diff(&p.path, staged)) {
+                        None => flash_error("Not a git repository"),
+                        Some(Ok(true)) => {}
+                        Some(Ok(false)) if staged => flash_ok("No staged changes"),
+                        Some(Ok(false)) => flash_ok("No unstaged changes"),
+                        Some(Err(e)) => flash_error(&format!("Diff failed: {e:#}")),
                     }
                 }
             }
-            c if (32..=126).contains(&c) => {
-                state.filter_text.push(c as u8 as char);
+            // # for picking one of the tags in the details to filter by
+            35 => {
+                let has_tags = details_project(&state)
+                    .and_then(|p| p.cfg.as_ref())
+                    .is_some_and(|c| !c.tags.is_empty());
+                if has_tags {
+                    state.tag_pick = true;
+                } else {
+                    flash_error("No tags to filter by");
+                }
+            }
+            // @ for switching between relative and absolute dates
+            64 => state.absolute_times = !state.absolute_times,
+            // B for packing the project into a tarball
+            66 => {
+                if let Some(p) = current_project(&state).cloned() {
+                    spawn_tarball(&mut state, &p);
+                    flash_ok(&format!("Packaging {}…", display_name(&p)));
+                }
+            }
+            // H for grouping under date headers
+            72 => {
+                let selected_path = current_project(&state).map(|p| p.path.clone());
+                state.group_by_date = !state.group_by_date;
+                rebuild_filter(&mut state);
+                if let Some(path) = selected_path {
+                    select_path(&mut state, &path);
+                }
+            }
+            // s for cycling the sort order
+            115 => {
+                let selected_path = current_project(&state).map(|p| p.path.clone());
+                state.sort = state.sort.next();
                 rebuild_filter(&mut state);
+                if let Some(path) = selected_path {
+                    select_path(&mut state, &path);
+                }
+            }
+            // r for rescanning all roots, R (shift) also bypasses the git cache and
+            // re-reads .fylexignore
+            82 | 114 => {
+                if ch == 82 {
+                    let (globs, warnings) = load_ignore_file();
+                    state.ignore_globs = globs;
+                    for msg in &warnings {
+                        flash_error(msg);
+                    }
+                }
+                match reload_projects(&mut state, ch == 82) {
+                    Ok(skipped) => {
+                        for msg in &skipped {
+                            flash_error(msg);
+                        }
+                        let how = if ch == 82 { " (git cache bypassed)" } else { "" };
+                        flash_ok(&format!("Reloaded {} projects{how}", state.projects.len()));
+                    }
+                    Err(e) => flash_error(&format!("Reload failed: {e:#}")),
+                }
             }
             _ => {}
         }
     }
 
     endwin();
+    if let Err(e) = persist_settings(&state) {
+        eprintln!("fylex: saving settings failed: {e:#}");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_project(root: &Path, dir: &str, config: Option<&str>) {
+        let path = root.join(dir);
+        fs::create_dir(&path).unwrap();
+        if let Some(text) = config {
+            fs::write(path.join(CONFIG_NAME), text).unwrap();
+        }
+    }
+
+    fn scan(root: &Path) -> Vec<Project> {
+        let config = AppConfig::default();
+        let globs = globset::GlobSet::empty();
+        let opts = ScanOptions::new(&config, false, &globs);
+        let (projects, skipped) = scan_projects(&[root.to_path_buf()], None, &opts).unwrap();
+        assert!(skipped.is_empty(), "{skipped:?}");
+        projects
+    }
+
+    fn filter_names(state: &mut AppState, filter: &str) -> Vec<String> {
+        state.filter_text = filter.to_string();
+        rebuild_filter(state);
+        state.filtered.iter().map(|&i| dir_name(&state.projects[i].path)).collect()
+    }
+
+    #[test]
+    fn scan_reads_valid_missing_and_malformed_configs() {
+        let root = tempfile::tempdir().unwrap();
+        add_project(root.path(), "valid", Some(r#"{"name": "Valid", "tags": ["rust"]}"#));
+        add_project(root.path(), "bare", None);
+        add_project(root.path(), "broken", Some("{ not json"));
+        fs::write(root.path().join("notes.txt"), "not a project").unwrap();
+
+        let projects = scan(root.path());
+        let names: Vec<String> = projects.iter().map(|p| dir_name(&p.path)).collect();
+        assert_eq!(names, ["bare", "broken", "valid"]);
+
+        let [bare, broken, valid] = &projects[..] else {
+            unreachable!();
+        };
+        assert!(bare.cfg.is_none() && bare.cfg_error.is_none());
+        assert!(broken.cfg.is_none());
+        assert!(broken.cfg_error.as_deref().unwrap().contains(CONFIG_NAME));
+        let cfg = valid.cfg.as_ref().unwrap();
+        assert_eq!(cfg.name, "Valid");
+        assert_eq!(cfg.tags, ["rust"]);
+        assert!(valid.cfg_error.is_none());
+    }
+
+    #[test]
+    fn scan_skips_dot_directories_and_ignored_names() {
+        let root = tempfile::tempdir().unwrap();
+        add_project(root.path(), "app", None);
+        add_project(root.path(), ".cache", None);
+        add_project(root.path(), "node_modules", None);
+        let config = AppConfig {
+            ignore: vec!["node_modules".to_string()],
+            ..Default::default()
+        };
+
+        let globs = globset::GlobSet::empty();
+        let roots = [root.path().to_path_buf()];
+
+        let (projects, _) =
+            scan_projects(&roots, None, &ScanOptions::new(&config, false, &globs)).unwrap();
+        assert_eq!(projects.len(), 1);
+        let (projects, _) =
+            scan_projects(&roots, None, &ScanOptions::new(&config, true, &globs)).unwrap();
+        assert_eq!(projects.len(), 2);
+    }
+
+    #[test]
+    fn ignore_file_globs_skip_directories_and_bad_lines_warn() {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["app", "app-backup", "archive-2023", "tools"] {
+            add_project(root.path(), dir, None);
+        }
+        let (globs, warnings) = parse_ignore("# old stuff\n*-backup\n/archive-*/\n[oops\n!tools\n");
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert!(warnings[0].starts_with(".fylexignore line 4"));
+
+        let config = AppConfig::default();
+        let roots = [root.path().to_path_buf()];
+        let (projects, _) =
+            scan_projects(&roots, None, &ScanOptions::new(&config, false, &globs)).unwrap();
+        let names: Vec<String> = projects.iter().map(|p| dir_name(&p.path)).collect();
+        assert_eq!(names, ["app", "tools"]);
+    }
+
+    #[test]
+    fn config_round_trips_and_missing_config_is_none() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_config(dir.path()).unwrap().is_none());
+
+        let mut cfg = default_config(dir.path());
+        cfg.description = "A test project".to_string();
+        cfg.tags = vec!["cli".to_string(), "rust".to_string()];
+        write_config(dir.path(), &cfg).unwrap();
+
+        let read = read_config(dir.path()).unwrap().unwrap();
+        assert_eq!(read.name, cfg.name);
+        assert_eq!(read.description, "A test project");
+        assert_eq!(read.tags, ["cli", "rust"]);
+        assert_eq!(read.created_at, cfg.created_at);
+    }
+
+    #[test]
+    fn config_fields_default_and_unknown_ones_are_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(CONFIG_NAME), r#"{"name": "untagged"}"#).unwrap();
+        let cfg = read_config(dir.path()).unwrap().unwrap();
+        assert_eq!(cfg.name, "untagged");
+        assert!(cfg.tags.is_empty());
+
+        let text = r#"{"name": "shared", "tags": ["team"], "owner": "someone"}"#;
+        fs::write(dir.path().join(CONFIG_NAME), text).unwrap();
+        let cfg = read_config(dir.path()).unwrap().unwrap();
+        assert_eq!(cfg.name, "shared");
+        assert_eq!(cfg.tags, ["team"]);
+    }
+
+    #[test]
+    fn malformed_config_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(CONFIG_NAME), r#"{"name": 3}"#).unwrap();
+        assert!(read_config(dir.path()).is_err());
+    }
+
+    #[test]
+    fn filter_matches_name_and_tags() {
+        let root = tempfile::tempdir().unwrap();
+        add_project(root.path(), "alpha", Some(r#"{"name": "alpha", "tags": ["cli"]}"#));
+        add_project(root.path(), "beta", Some(r#"{"name": "beta", "tags": ["alpine"]}"#));
+        add_project(root.path(), "gamma", None);
+        let mut state = AppState {
+            projects: scan(root.path()),
+            ..Default::default()
+        };
+
+        assert_eq!(filter_names(&mut state, ""), ["alpha", "beta", "gamma"]);
+        // Plain text looks at the name and the tags
+        assert_eq!(filter_names(&mut state, "alp"), ["alpha", "beta"]);
+        assert_eq!(filter_names(&mut state, "name:alp"), ["alpha"]);
+        assert_eq!(filter_names(&mut state, "tag:alp"), ["beta"]);
+        assert_eq!(filter_names(&mut state, "tag:cli name:alpha"), ["alpha"]);
+        assert!(filter_names(&mut state, "tag:cli name:beta").is_empty());
+        assert!(filter_names(&mut state, "zzz").is_empty());
+    }
+
+    #[test]
+    fn backspacing_multibyte_filter_text_is_safe() {
+        let root = tempfile::tempdir().unwrap();
+        add_project(root.path(), "café", None);
+        let mut state = AppState {
+            projects: scan(root.path()),
+            ..Default::default()
+        };
+
+        assert_eq!(filter_names(&mut state, "café ✓日本🦀"), Vec::<String>::new());
+        state.mode = Mode::Filter;
+        // One press per character, however many bytes it takes
+        for _ in 0..4 {
+            handle_filter_key(&mut state, 127);
+        }
+        assert_eq!(state.filter_text, "café ");
+        assert_eq!(state.filtered.len(), 1);
+        for _ in 0..10 {
+            handle_filter_key(&mut state, KEY_BACKSPACE);
+        }
+        assert!(state.filter_text.is_empty());
+        assert_eq!(state.filtered.len(), 1);
+    }
+
+    #[test]
+    fn filter_is_case_insensitive_unless_asked() {
+        let root = tempfile::tempdir().unwrap();
+        add_project(root.path(), "Alpha", None);
+        let mut state = AppState {
+            projects: scan(root.path()),
+            ..Default::default()
+        };
+
+        assert_eq!(filter_names(&mut state, "alpha"), ["Alpha"]);
+        state.case_sensitive = true;
+        assert!(filter_names(&mut state, "alpha").is_empty());
+        assert_eq!(filter_names(&mut state, "Alpha"), ["Alpha"]);
+    }
+
+    #[test]
+    fn dirty_sort_ranks_states_explicitly() {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["clean", "modified", "nogit", "submodules", "unknown"] {
+            add_project(root.path(), dir, None);
+        }
+        let mut projects = scan(root.path());
+        for (p, state) in projects.iter_mut().zip([Some(1), Some(2), None, Some(3), Some(4)]) {
+            p.git_state = state;
+        }
+        let usage = HashMap::new();
+        projects.sort_by(|a, b| sort_cmp(SortMode::Dirty, &usage, a, b));
+        let names: Vec<String> = projects.iter().map(|p| dir_name(&p.path)).collect();
+        assert_eq!(names, ["modified", "submodules", "unknown", "clean", "nogit"]);
+    }
+
+    #[test]
+    fn display_name_falls_back_to_the_whole_path() {
+        let root = tempfile::tempdir().unwrap();
+        add_project(root.path(), "sub", None);
+        for path in [root.path().join("sub").join(".."), PathBuf::from("/")] {
+            let p = load_project(path.clone(), root.path(), false, false);
+            assert_eq!(display_name(&p), path.to_string_lossy());
+        }
+    }
+
+    #[test]
+    fn project_names_are_validated() {
+        let root = tempfile::tempdir().unwrap();
+        assert_eq!(
+            validate_project_name(root.path(), "new-project").unwrap(),
+            root.path().join("new-project")
+        );
+        for bad in ["", "  ", " padded", "a/b", "../escape", ".hidden", "tab\there"] {
+            assert!(validate_project_name(root.path(), bad).is_err(), "{bad:?} was accepted");
+        }
+    }
+}